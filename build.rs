@@ -0,0 +1,4 @@
+// `sqlx::migrate!` で埋め込むマイグレーションが変わったときに、再ビルドする
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,3 @@
+ALTER TABLE `regexps`
+  ADD COLUMN IF NOT EXISTS `updated_at` DATETIME NULL DEFAULT NULL,
+  ADD COLUMN IF NOT EXISTS `call_count` INT UNSIGNED NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS `regexp_revisions` (
+  `id` INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  `key` CHAR(36) NOT NULL,
+  `revision` INT UNSIGNED NOT NULL,
+  `action` VARCHAR(16) NOT NULL,
+  `regexp` TEXT NOT NULL,
+  `user_id` CHAR(36) NOT NULL,
+  `user_name` VARCHAR(32) NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE KEY `key_revision` (`key`, `revision`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_unicode_ci;
@@ -0,0 +1 @@
+ALTER TABLE `regexps` ADD COLUMN IF NOT EXISTS `deleted_at` DATETIME NULL DEFAULT NULL;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS `regexp_maintainers` (
+  `key` CHAR(36) NOT NULL,
+  `user_id` CHAR(36) NOT NULL,
+  `user_name` VARCHAR(32) NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (`key`, `user_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_unicode_ci;
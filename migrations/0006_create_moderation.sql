@@ -0,0 +1,19 @@
+ALTER TABLE `regexps` ADD COLUMN IF NOT EXISTS `locked` BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS `banned_users` (
+  `user_id` CHAR(36) NOT NULL PRIMARY KEY,
+  `user_name` VARCHAR(32) NOT NULL,
+  `reason` TEXT NULL DEFAULT NULL,
+  `banned_by` CHAR(36) NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_unicode_ci;
+
+CREATE TABLE IF NOT EXISTS `audit_logs` (
+  `id` INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  `user_id` CHAR(36) NOT NULL,
+  `user_name` VARCHAR(32) NOT NULL,
+  `action` VARCHAR(16) NOT NULL,
+  `target` VARCHAR(255) NOT NULL,
+  `detail` TEXT NULL DEFAULT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_unicode_ci;
@@ -0,0 +1,4 @@
+-- チャンネルやユーザーの key は `#{UUID}/name` の形で保存するので、UUID より長くなる
+ALTER TABLE `regexps` MODIFY `key` VARCHAR(80) NOT NULL;
+ALTER TABLE `regexp_revisions` MODIFY `key` VARCHAR(80) NOT NULL;
+ALTER TABLE `regexp_maintainers` MODIFY `key` VARCHAR(80) NOT NULL;
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS `regexp_aliases` (
+  `alias` VARCHAR(80) NOT NULL PRIMARY KEY,
+  `key` VARCHAR(80) NOT NULL,
+  `user_id` CHAR(36) NOT NULL,
+  `user_name` VARCHAR(32) NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX `key` (`key`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_unicode_ci;
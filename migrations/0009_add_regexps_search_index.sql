@@ -0,0 +1 @@
+CREATE FULLTEXT INDEX IF NOT EXISTS `search` ON `regexps` (`key`, `regexp`);
@@ -0,0 +1,12 @@
+ALTER TABLE `regexps` ADD COLUMN IF NOT EXISTS `description` TEXT NULL DEFAULT NULL AFTER `regexp`;
+
+-- 説明からも探せるようにする
+DROP INDEX IF EXISTS `search` ON `regexps`;
+CREATE FULLTEXT INDEX `search` ON `regexps` (`key`, `regexp`, `description`);
+
+CREATE TABLE IF NOT EXISTS `regexp_tags` (
+  `key` VARCHAR(80) NOT NULL,
+  `tag` VARCHAR(32) NOT NULL,
+  PRIMARY KEY (`key`, `tag`),
+  INDEX `tag` (`tag`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_unicode_ci;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS `regexp_calls` (
+  `id` INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  `key` VARCHAR(80) NOT NULL,
+  `user_id` CHAR(36) NOT NULL,
+  `user_name` VARCHAR(32) NOT NULL,
+  `channel_id` CHAR(36) NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX `key` (`key`),
+  INDEX `user_id` (`user_id`),
+  INDEX `created_at` (`created_at`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_unicode_ci;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS `regexp_favorites` (
+  `user_id` CHAR(36) NOT NULL,
+  `key` VARCHAR(80) NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (`user_id`, `key`),
+  INDEX `key` (`key`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_unicode_ci;
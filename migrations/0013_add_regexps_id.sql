@@ -0,0 +1,3 @@
+-- `/lucky` で無作為に選ぶときに、インデックスを使って読めるようにする連番
+ALTER TABLE `regexps`
+  ADD COLUMN IF NOT EXISTS `id` INT UNSIGNED NOT NULL AUTO_INCREMENT UNIQUE;
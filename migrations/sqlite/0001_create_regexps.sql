@@ -0,0 +1,11 @@
+-- SQLite では、正規表現の保存・呼び出し・削除・一覧だけを扱う
+CREATE TABLE IF NOT EXISTS `regexps` (
+  `key` TEXT NOT NULL PRIMARY KEY,
+  `regexp` TEXT NOT NULL,
+  `description` TEXT NULL DEFAULT NULL,
+  -- `,omikuji,fortune,` のように、前後にもカンマをつけて保存する
+  `tags` TEXT NOT NULL DEFAULT ',',
+  `user_id` TEXT NOT NULL,
+  `user_name` TEXT NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,216 @@
+//! BOT を起動せずに、データベースの正規表現を書き出したり取り込んだりするコマンド
+//!
+//! ```text
+//! bot-randregex export [--yaml] [ファイル]
+//! bot-randregex import [--skip | --overwrite | --rename] [ファイル]
+//! ```
+//!
+//! ファイルを省略した場合は、標準出力に書き出し、標準入力から取り込む
+
+use std::{
+    fs,
+    io::{self, Read},
+};
+
+use anyhow::Context;
+use sqlx::MySqlPool;
+
+use crate::{
+    config::{BOT_NAME, BOT_USER_ID},
+    handler::{validate_pattern, validate_tag},
+    model::{
+        db::{self, Conflict, Imported},
+        dump::{self, Format},
+        key,
+    },
+};
+
+pub async fn run(pool: &MySqlPool, args: &[String]) -> anyhow::Result<()> {
+    let (command, args) = args.split_first().context("サブコマンドが必須です")?;
+    match command.as_str() {
+        "export" => export(pool, args).await,
+        "import" => import(pool, args).await,
+        unknown => anyhow::bail!("不明なサブコマンドです: {}", unknown),
+    }
+}
+
+const EXPORT_USAGE: &str = "bot-randregex export [--yaml] [ファイル]";
+const IMPORT_USAGE: &str = "bot-randregex import [--skip | --overwrite | --rename] [ファイル]";
+
+/// `export` に続く引数を解釈し、形式と書き出すファイルを返す
+/// 知らないオプションをファイル名として扱わないように、`--` で始まるものは断る
+fn parse_export_args(args: &[String]) -> anyhow::Result<(Format, Option<&str>)> {
+    let mut format = Format::Json;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => format = Format::Json,
+            "--yaml" => format = Format::Yaml,
+            arg if arg.starts_with("--") => {
+                anyhow::bail!("不明なオプションです: {}\n使い方: {}", arg, EXPORT_USAGE)
+            }
+            arg => path = Some(arg),
+        }
+    }
+    Ok((format, path))
+}
+
+/// `import` に続く引数を解釈し、同じ key があった場合の扱いと読み込むファイルを返す
+fn parse_import_args(args: &[String]) -> anyhow::Result<(Conflict, Option<&str>)> {
+    let mut conflict = Conflict::default();
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--skip" => conflict = Conflict::Skip,
+            "--overwrite" => conflict = Conflict::Overwrite,
+            "--rename" => conflict = Conflict::Rename,
+            arg if arg.starts_with("--") => {
+                anyhow::bail!("不明なオプションです: {}\n使い方: {}", arg, IMPORT_USAGE)
+            }
+            arg => path = Some(arg),
+        }
+    }
+    Ok((conflict, path))
+}
+
+/// チャンネルやユーザーの key も含めて、すべての正規表現を書き出す
+async fn export(pool: &MySqlPool, args: &[String]) -> anyhow::Result<()> {
+    let (format, path) = parse_export_args(args)?;
+
+    let filter = db::ListFilter {
+        visible_prefixes: vec!["#".to_string(), "~".to_string()],
+        ..Default::default()
+    };
+    let patterns = db::export(pool, &filter).await?;
+    let count = patterns.len();
+    let content = dump::to_string(patterns, format)?;
+    match path {
+        Some(path) => fs::write(path, content)?,
+        None => println!("{}", content),
+    }
+    eprintln!("Exported {} patterns", count);
+
+    Ok(())
+}
+
+/// 取り込む前に、key と正規表現と作成者を確かめる
+///
+/// BOT の外では `~me/` や `#gps/times/` を解決できないので、保存するときの形になっている key だけを取り込む
+/// ユーザーの key は、そのユーザーが作成したものだけを取り込む
+fn check(pattern: &dump::Pattern, user_id: &str) -> Result<(), String> {
+    if !key::is_resolved(&pattern.key) {
+        return Err("~me/ や #channel/ の key は、UUID で書かれている必要があります".to_string());
+    }
+    key::validate(&pattern.key)?;
+    if !key::is_uuid(user_id) {
+        return Err(format!(
+            "作成者の user_id が UUID ではありません: {}",
+            user_id
+        ));
+    }
+    if key::user_id(&pattern.key).is_some_and(|id| id != user_id) {
+        return Err("ユーザーの key の作成者が、そのユーザーではありません".to_string());
+    }
+    pattern.tags.iter().try_for_each(|tag| validate_tag(tag))?;
+    validate_pattern(&pattern.regexp)
+}
+
+/// 書き出された正規表現を取り込む
+/// 作成者が書かれていないものは、この BOT が保存したものとする
+async fn import(pool: &MySqlPool, args: &[String]) -> anyhow::Result<()> {
+    let (conflict, path) = parse_import_args(args)?;
+
+    let text = match path {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        }
+    };
+    let patterns = dump::parse(&text)?;
+
+    let (mut saved, mut skipped) = (0, 0);
+    for pattern in &patterns {
+        let user_id = pattern.user_id.as_deref().unwrap_or(BOT_USER_ID);
+        let user_name = pattern.user_name.as_deref().unwrap_or(BOT_NAME);
+        if let Err(e) = check(pattern, user_id) {
+            eprintln!("Skipped: {}: {}", pattern.key, e);
+            skipped += 1;
+            continue;
+        }
+        match db::import(pool, pattern, conflict, user_id, user_name).await? {
+            Imported::Saved | Imported::Overwritten => saved += 1,
+            Imported::Renamed(renamed) => {
+                eprintln!("Renamed: {} -> {}", pattern.key, renamed);
+                saved += 1;
+            }
+            Imported::Skipped => {
+                eprintln!("Skipped: {}", pattern.key);
+                skipped += 1;
+            }
+        }
+    }
+    eprintln!("Imported {} patterns ({} skipped)", saved, skipped);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(key: &str, regexp: &str) -> dump::Pattern {
+        dump::Pattern {
+            key: key.to_string(),
+            regexp: regexp.to_string(),
+            description: None,
+            tags: vec![],
+            user_id: None,
+            user_name: None,
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let export = args(&["--yaml", "dump.yaml"]);
+        let (format, path) = parse_export_args(&export).unwrap();
+        assert_eq!(format, Format::Yaml);
+        assert_eq!(path, Some("dump.yaml"));
+        let import = args(&["--rename", "dump.json"]);
+        let (conflict, path) = parse_import_args(&import).unwrap();
+        assert_eq!(conflict, Conflict::Rename);
+        assert_eq!(path, Some("dump.json"));
+        assert_eq!(parse_import_args(&[]).unwrap(), (Conflict::Skip, None));
+
+        // 打ち間違えたオプションを、ファイル名として書き出したり読み込んだりしない
+        assert!(parse_export_args(&args(&["--yml"])).is_err());
+        assert!(parse_import_args(&args(&["--overwirte", "dump.json"])).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let private = format!("~{}/omikuji", BOT_USER_ID);
+        assert!(check(&pattern("omikuji", "(大|小)吉|凶"), BOT_USER_ID).is_ok());
+        assert!(check(&pattern(&private, "吉"), BOT_USER_ID).is_ok());
+
+        assert!(check(&pattern("~me/omikuji", "吉"), BOT_USER_ID).is_err());
+        assert!(check(&pattern("#gps/times/omikuji", "吉"), BOT_USER_ID).is_err());
+        assert!(check(&pattern("omikuji", "(吉"), BOT_USER_ID).is_err());
+        assert!(check(&pattern("omikuji", "吉"), "SSlime").is_err());
+        let other = "1".repeat(36);
+        assert!(check(&pattern(&private, "吉"), &other).is_err());
+
+        let mut tagged = pattern("omikuji", "吉");
+        tagged.tags = vec!["占い".to_string()];
+        assert!(check(&tagged, BOT_USER_ID).is_ok());
+        for tag in ["", &"a".repeat(33)] {
+            tagged.tags = vec![tag.to_string()];
+            assert!(check(&tagged, BOT_USER_ID).is_err(), "{:?}", tag);
+        }
+    }
+}
@@ -1,6 +1,12 @@
-use std::env;
+use std::{env, str::FromStr, time::Duration};
 
 use once_cell::sync::Lazy;
+use sqlx::MySqlPool;
+
+use crate::{
+    model::store::{Backend, PatternStore},
+    parser::Limits,
+};
 
 /// この BOT の NAME
 pub const BOT_NAME: &str = "BOT_SRegexp";
@@ -17,4 +23,65 @@ pub static BOT_ACCESS_TOKEN: Lazy<String> = Lazy::new(|| {
     env::var("BOT_ACCESS_TOKEN").expect("BOT_ACCESS_TOKEN is required")
 });
 
-pub type Resource = sqlx::MySqlPool;
+/// ハンドラーが受け取るリソース
+pub struct Resource {
+    /// 正規表現の保存・呼び出し・削除・一覧に使う
+    pub store: Box<dyn PatternStore>,
+    /// 履歴や別名などを扱うためのもので、MariaDB を使っている場合だけ存在する
+    pub pool: Option<MySqlPool>,
+}
+
+/// 環境変数 `key` を読み、設定されていないか不正な値の場合は `default` を返す
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    dotenv::dotenv().ok();
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// パターンの解析に関する制限
+pub static PARSER_LIMITS: Lazy<Limits> = Lazy::new(|| {
+    let default = Limits::default();
+    Limits {
+        pattern_length: env_or("PATTERN_LENGTH_LIMIT", default.pattern_length),
+        depth: env_or("PATTERN_DEPTH_LIMIT", default.depth),
+        nodes: env_or("PATTERN_NODE_LIMIT", default.nodes),
+        repeat: env_or("PATTERN_REPEAT_LIMIT", default.repeat),
+    }
+});
+
+/// 管理者の USER ID の一覧
+/// 環境変数 `ADMIN_USER_IDS` にカンマ区切りで指定する
+pub static ADMIN_USER_IDS: Lazy<Vec<String>> = Lazy::new(|| {
+    dotenv::dotenv().ok();
+    env::var("ADMIN_USER_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+});
+
+/// 削除された key を復元できるように残しておく日数
+pub static TOMBSTONE_RETENTION_DAYS: Lazy<u32> =
+    Lazy::new(|| env_or("TOMBSTONE_RETENTION_DAYS", 30));
+
+/// 保存しておく日数が過ぎた削除済みの key を、完全に消す間隔
+/// 0 を指定した場合は 1 分にする
+pub static TOMBSTONE_PURGE_INTERVAL: Lazy<Duration> = Lazy::new(|| {
+    let minutes: u64 = env_or("TOMBSTONE_PURGE_INTERVAL_MINUTES", 60);
+    Duration::from_secs(60 * minutes.max(1))
+});
+
+/// 1 回の文字列の生成にかけられる最大の時間
+pub static GENERATION_TIMEOUT: Lazy<Duration> =
+    Lazy::new(|| Duration::from_millis(env_or("GENERATION_TIMEOUT_MS", 1000)));
+
+/// 正規表現を保存する場所
+/// 環境変数 `STORE_BACKEND` に `mariadb`, `sqlite`, `memory` のいずれかを指定する
+pub static STORE_BACKEND: Lazy<Backend> = Lazy::new(|| env_or("STORE_BACKEND", Backend::MariaDb));
+
+/// `STORE_BACKEND` が `sqlite` の場合に接続する URL
+pub static SQLITE_URL: Lazy<String> =
+    Lazy::new(|| env_or("SQLITE_URL", "sqlite://s_regexp.db?mode=rwc".to_string()));
@@ -0,0 +1,290 @@
+use crate::model::{ClassElement, Expression, Factor, Literal, Term, TermSuffix, Terms, Token};
+
+/// パターンの説明を木構造で表したもの
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub label: String,
+    pub children: Vec<Explanation>,
+}
+
+impl Explanation {
+    fn leaf(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            children: Vec::new(),
+        }
+    }
+
+    fn node(label: impl Into<String>, children: Vec<Explanation>) -> Self {
+        Self {
+            label: label.into(),
+            children,
+        }
+    }
+
+    /// markdown の入れ子になったリストとして出力する
+    pub fn render(&self) -> String {
+        let mut lines = Vec::new();
+        self.render_into(0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn render_into(&self, depth: usize, lines: &mut Vec<String>) {
+        lines.push(format!("{}- {}", "  ".repeat(depth), self.label));
+        for child in &self.children {
+            child.render_into(depth + 1, lines);
+        }
+    }
+}
+
+pub trait Explain {
+    fn explain(&self) -> Explanation;
+}
+
+/// 正規表現中で、ただの 1 文字として扱われる場合はその文字を返す
+fn plain_char(literal: &Literal) -> Option<char> {
+    match *literal {
+        Literal::Char(c) => Some(c),
+        Literal::Escape(c) if !c.is_ascii_alphanumeric() => Some(c),
+        Literal::Escape(_) => None,
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("「{}」", s)
+}
+
+fn text(s: &str) -> Explanation {
+    if s.chars().count() == 1 {
+        Explanation::leaf(format!("文字 {}", quote(s)))
+    } else {
+        Explanation::leaf(format!("文字列 {}", quote(s)))
+    }
+}
+
+fn fraction(n: usize) -> String {
+    if n == 1 {
+        "1".to_string()
+    } else {
+        format!("1/{}", n)
+    }
+}
+
+impl Explain for Literal {
+    fn explain(&self) -> Explanation {
+        if let Some(c) = plain_char(self) {
+            return text(&c.to_string());
+        }
+        match self {
+            Literal::Escape('d') => Explanation::leaf("数字 1 文字 (0〜9 の 10 通り)"),
+            Literal::Escape('w') => {
+                Explanation::leaf("英数字 1 文字 (a〜z, A〜Z, 0〜9 の 62 通り)")
+            }
+            Literal::Escape(c) => {
+                Explanation::leaf(format!("不正なエスケープ \\{} (生成に失敗します)", c))
+            }
+            Literal::Char(_) => unreachable!(),
+        }
+    }
+}
+
+impl ClassElement {
+    fn describe(&self) -> String {
+        match self {
+            ClassElement::Range(a, b) => format!("{}〜{}", a, b),
+            ClassElement::Literal(Literal::Escape('d')) => "0〜9".to_string(),
+            ClassElement::Literal(Literal::Escape('w')) => "a〜z, A〜Z, 0〜9".to_string(),
+            ClassElement::Literal(l) => match plain_char(l) {
+                Some(c) => c.to_string(),
+                None => format!("\\{} (不正なエスケープ)", l.as_char()),
+            },
+        }
+    }
+}
+
+impl Literal {
+    fn as_char(&self) -> char {
+        match *self {
+            Literal::Char(c) | Literal::Escape(c) => c,
+        }
+    }
+}
+
+impl Explain for Token {
+    fn explain(&self) -> Explanation {
+        match self {
+            Token::Literal(l) => l.explain(),
+            Token::Class(elements) => {
+                let size = elements.iter().map(|e| e.size()).sum::<usize>();
+                let described = elements
+                    .iter()
+                    .map(|e| e.describe())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Explanation::leaf(format!("{} から 1 文字 ({} 通り)", quote(&described), size))
+            }
+        }
+    }
+}
+
+impl Explain for Factor {
+    fn explain(&self) -> Explanation {
+        match self {
+            Factor::Token(t) => t.explain(),
+            Factor::Group(e) | Factor::FixedGroup(e) => e.explain(),
+        }
+    }
+}
+
+impl TermSuffix {
+    fn describe(&self, fixed: bool) -> String {
+        let target = if fixed {
+            "次を 1 度だけ生成し、その同じ文字列を"
+        } else {
+            "次を"
+        };
+        match *self {
+            TermSuffix::Question => {
+                "50% の確率で次を生成 (残りの 50% は何も生成しない)".to_string()
+            }
+            TermSuffix::Asterisk => {
+                format!(
+                    "{} 0 回以上繰り返す (毎回 50% の確率で繰り返しを終える)",
+                    target
+                )
+            }
+            TermSuffix::Plus => format!(
+                "{} 1 回以上繰り返す (1 回目の後、毎回 50% の確率で繰り返しを終える)",
+                target
+            ),
+            TermSuffix::Range(a, b) if a == b => format!("{} {} 回繰り返す", target, a),
+            TermSuffix::Range(a, b) => format!(
+                "{} {}〜{} 回繰り返す (回数はそれぞれ {} の確率)",
+                target,
+                a,
+                b,
+                fraction(b.saturating_sub(a).saturating_add(1))
+            ),
+            TermSuffix::OpenRange(a) => format!(
+                "{} {} 回以上繰り返す ({} 回の後、毎回 50% の確率で繰り返しを終える)",
+                target, a, a
+            ),
+            TermSuffix::Repeat(n) => format!("{} {} 回繰り返す", target, n),
+        }
+    }
+}
+
+impl Explain for Term {
+    fn explain(&self) -> Explanation {
+        match self {
+            Term::Factor(f) => f.explain(),
+            Term::WithSuffix(f, s) => {
+                let fixed = matches!(f, Factor::FixedGroup(_));
+                Explanation::node(s.describe(fixed), vec![f.explain()])
+            }
+        }
+    }
+}
+
+impl Explain for Terms {
+    fn explain(&self) -> Explanation {
+        match self {
+            Terms::Concat(terms) => {
+                let mut items = Vec::new();
+                let mut chars = String::new();
+                for term in terms {
+                    if let Term::Factor(Factor::Token(Token::Literal(l))) = term {
+                        if let Some(c) = plain_char(l) {
+                            chars.push(c);
+                            continue;
+                        }
+                    }
+                    if !chars.is_empty() {
+                        items.push(text(&chars));
+                        chars.clear();
+                    }
+                    items.push(term.explain());
+                }
+                if !chars.is_empty() {
+                    items.push(text(&chars));
+                }
+
+                if items.len() == 1 {
+                    items.remove(0)
+                } else {
+                    Explanation::node("次を順につなげたもの", items)
+                }
+            }
+        }
+    }
+}
+
+impl Explain for Expression {
+    fn explain(&self) -> Explanation {
+        match self {
+            Expression::Union(t) if t.len() == 1 => t[0].explain(),
+            Expression::Union(t) => Explanation::node(
+                format!("次のいずれか 1 つ (それぞれ {} の確率)", fraction(t.len())),
+                t.iter().map(|t| t.explain()).collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn explain(s: &str) -> String {
+        parse(s).unwrap().explain().render()
+    }
+
+    #[test]
+    fn test_explain_literals() {
+        assert_eq!(explain("a"), "- 文字 「a」");
+        assert_eq!(explain(r"ab\["), "- 文字列 「ab[」");
+        assert_eq!(explain(r"\d"), "- 数字 1 文字 (0〜9 の 10 通り)");
+    }
+
+    #[test]
+    fn test_explain_word_class() {
+        // 文字クラスの中の `\w` も、そのままの `\w` と同じ 62 通りになる
+        assert_eq!(
+            explain(r"\w"),
+            "- 英数字 1 文字 (a〜z, A〜Z, 0〜9 の 62 通り)"
+        );
+        assert_eq!(
+            explain(r"[\w]"),
+            "- 「a〜z, A〜Z, 0〜9」 から 1 文字 (62 通り)"
+        );
+    }
+
+    #[test]
+    fn test_explain_union_and_repeat() {
+        assert_eq!(
+            explain("hoge|[a-z]{3,5}"),
+            [
+                "- 次のいずれか 1 つ (それぞれ 1/2 の確率)",
+                "  - 文字列 「hoge」",
+                "  - 次を 3〜5 回繰り返す (回数はそれぞれ 1/3 の確率)",
+                "    - 「a〜z」 から 1 文字 (26 通り)",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_explain_fixed_group() {
+        assert_eq!(
+            explain("x<[ab]>*"),
+            [
+                "- 次を順につなげたもの",
+                "  - 文字 「x」",
+                "  - 次を 1 度だけ生成し、その同じ文字列を 0 回以上繰り返す (毎回 50% の確率で繰り返しを終える)",
+                "    - 「a, b」 から 1 文字 (2 通り)",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -1,13 +1,103 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
 use crate::model::{ClassElement, Expression, Factor, Literal, Term, TermSuffix, Terms, Token};
 use anyhow::Result;
 use rand::Rng;
 
+/// 生成した文字列の文字数が上限を超えたことを表すエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TooLong {
+    pub limit: usize,
+}
+
+impl fmt::Display for TooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Generated text is too long: more than {} characters",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for TooLong {}
+
+/// 生成に時間がかかりすぎたことを表すエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedOut {
+    pub timeout: Duration,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Generation timed out: took more than {} ms",
+            self.timeout.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// 生成できる残りの文字数と時間
+///
+/// 上限を超えた時点で生成を打ち切るために、生成中に持ち回す
+#[derive(Debug, Clone)]
+pub struct Budget {
+    limit: usize,
+    remaining: usize,
+    timeout: Option<(Duration, Instant)>,
+}
+
+impl Budget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            remaining: limit,
+            timeout: None,
+        }
+    }
+
+    /// 今から `timeout` が経過したら生成を打ち切るようにする
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some((timeout, Instant::now() + timeout)),
+            ..self
+        }
+    }
+
+    fn check_deadline(&self) -> Result<()> {
+        match self.timeout {
+            Some((timeout, deadline)) if Instant::now() > deadline => {
+                Err(TimedOut { timeout }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// `n` 文字分を消費する
+    fn consume(&mut self, n: usize) -> Result<()> {
+        self.check_deadline()?;
+        match self.remaining.checked_sub(n) {
+            Some(remaining) => {
+                self.remaining = remaining;
+                Ok(())
+            }
+            None => Err(TooLong { limit: self.limit }.into()),
+        }
+    }
+}
+
 pub trait Generate {
-    fn generate(&self, rng: &mut impl Rng) -> Result<String>;
+    fn generate(&self, rng: &mut impl Rng, budget: &mut Budget) -> Result<String>;
 }
 
 impl Generate for Literal {
-    fn generate(&self, _rng: &mut impl Rng) -> Result<String> {
+    fn generate(&self, _rng: &mut impl Rng, _budget: &mut Budget) -> Result<String> {
         match self {
             Literal::Char(c) => Ok(c.to_string()),
             Literal::Escape('d') => Ok(rand::thread_rng().gen_range(0..=9).to_string()),
@@ -32,7 +122,7 @@ impl Generate for Literal {
 }
 
 impl Generate for ClassElement {
-    fn generate(&self, rng: &mut impl Rng) -> Result<String> {
+    fn generate(&self, rng: &mut impl Rng, budget: &mut Budget) -> Result<String> {
         match self {
             ClassElement::Range(a, b) => {
                 let a = *a as usize;
@@ -45,7 +135,7 @@ impl Generate for ClassElement {
                     .ok_or_else(|| anyhow::anyhow!("Invalid range"))?
                     .to_string())
             }
-            ClassElement::Literal(l) => l.generate(rng),
+            ClassElement::Literal(l) => l.generate(rng, budget),
         }
     }
 }
@@ -54,23 +144,24 @@ impl ClassElement {
         match *self {
             ClassElement::Range(a, b) => (b as usize) - (a as usize) + 1,
             ClassElement::Literal(Literal::Escape('d')) => 10,
-            ClassElement::Literal(Literal::Escape('w')) => 26 + 26 + 10 + 1,
+            ClassElement::Literal(Literal::Escape('w')) => 26 + 26 + 10,
             ClassElement::Literal(_) => 1,
         }
     }
 }
 
 impl Generate for Token {
-    fn generate(&self, rng: &mut impl Rng) -> Result<String> {
+    fn generate(&self, rng: &mut impl Rng, budget: &mut Budget) -> Result<String> {
+        budget.consume(1)?;
         match self {
-            Token::Literal(l) => l.generate(rng),
+            Token::Literal(l) => l.generate(rng, budget),
             Token::Class(c) => {
                 let sum = c.iter().map(|e| e.size()).sum::<usize>();
                 let mut r = rng.gen_range(0..sum);
                 for e in c {
                     let s = e.size();
                     if r < s {
-                        return e.generate(rng);
+                        return e.generate(rng, budget);
                     }
                     r -= s;
                 }
@@ -81,19 +172,19 @@ impl Generate for Token {
 }
 
 impl Generate for Factor {
-    fn generate(&self, rng: &mut impl Rng) -> Result<String> {
+    fn generate(&self, rng: &mut impl Rng, budget: &mut Budget) -> Result<String> {
         match self {
-            Factor::Token(t) => t.generate(rng),
-            Factor::Group(e) => e.generate(rng),
-            Factor::FixedGroup(e) => e.generate(rng),
+            Factor::Token(t) => t.generate(rng, budget),
+            Factor::Group(e) => e.generate(rng, budget),
+            Factor::FixedGroup(e) => e.generate(rng, budget),
         }
     }
 }
 
 impl Generate for Term {
-    fn generate(&self, rng: &mut impl Rng) -> Result<String> {
+    fn generate(&self, rng: &mut impl Rng, budget: &mut Budget) -> Result<String> {
         match self {
-            Term::Factor(f) => f.generate(rng),
+            Term::Factor(f) => f.generate(rng, budget),
             Term::WithSuffix(f, s) => {
                 let n = match s {
                     TermSuffix::Question => rng.gen_range(0..=1),
@@ -122,12 +213,19 @@ impl Generate for Term {
                     TermSuffix::Repeat(a) => *a,
                 };
                 match f {
+                    Factor::FixedGroup(_) if n == 0 => Ok(String::new()),
                     Factor::FixedGroup(_) => {
-                        let base = f.generate(rng)?;
-                        Ok((0..n).map(|_| base.clone()).collect::<Vec<_>>().join(""))
+                        let base = f.generate(rng, budget)?;
+                        // 1 回分は生成したときに消費しているので、残りの回数分を消費する
+                        budget.consume(base.chars().count().saturating_mul(n - 1))?;
+                        Ok(base.repeat(n))
                     }
                     _ => Ok((0..n)
-                        .map(|_| f.generate(rng))
+                        .map(|_| {
+                            // 空文字列を生成し続ける場合にも打ち切れるように、毎回確認する
+                            budget.check_deadline()?;
+                            f.generate(rng, budget)
+                        })
                         .collect::<Result<Vec<_>>>()?
                         .join("")),
                 }
@@ -137,11 +235,11 @@ impl Generate for Term {
 }
 
 impl Generate for Terms {
-    fn generate(&self, rng: &mut impl Rng) -> Result<String> {
+    fn generate(&self, rng: &mut impl Rng, budget: &mut Budget) -> Result<String> {
         match self {
             Terms::Concat(t) => t
                 .iter()
-                .map(|t| t.generate(rng))
+                .map(|t| t.generate(rng, budget))
                 .collect::<Result<Vec<_>>>()
                 .map(|v| v.join("")),
         }
@@ -149,12 +247,98 @@ impl Generate for Terms {
 }
 
 impl Generate for Expression {
-    fn generate(&self, rng: &mut impl Rng) -> Result<String> {
+    fn generate(&self, rng: &mut impl Rng, budget: &mut Budget) -> Result<String> {
         match self {
             Expression::Union(t) => {
                 let i = rng.gen_range(0..t.len());
-                t[i].generate(rng)
+                t[i].generate(rng, budget)
+            }
+        }
+    }
+}
+
+/// 生成すると必ず失敗する要素 (`\q` のような不正なエスケープや `[z-a]` のような範囲) がないかを調べる
+///
+/// 実際に生成すると選ばれなかった部分は調べられないので、構文木をすべてたどる
+pub fn check(expression: &Expression) -> Result<()> {
+    fn literal(l: &Literal) -> Result<()> {
+        match l {
+            Literal::Escape('d' | 'w') => Ok(()),
+            Literal::Escape(c) if c.is_ascii_alphanumeric() => {
+                anyhow::bail!("Invalid escape: \\{}", c)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    let Expression::Union(terms) = expression;
+    for Terms::Concat(t) in terms {
+        for t in t {
+            let (Term::Factor(f) | Term::WithSuffix(f, _)) = t;
+            match f {
+                Factor::Token(Token::Literal(l)) => literal(l)?,
+                Factor::Token(Token::Class(elements)) => {
+                    for e in elements {
+                        match e {
+                            ClassElement::Range(a, b) => {
+                                anyhow::ensure!(a <= b, "Invalid range: {}-{}", a, b)
+                            }
+                            ClassElement::Literal(l) => literal(l)?,
+                        }
+                    }
+                }
+                Factor::Group(e) | Factor::FixedGroup(e) => check(e)?,
             }
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn generate(pattern: &str, limit: usize) -> Result<String> {
+        parse(pattern)
+            .unwrap()
+            .generate(&mut rand::thread_rng(), &mut Budget::new(limit))
+    }
+
+    #[test]
+    fn test_check() {
+        for pattern in ["a\\d\\w", "[\\d-]", "\\.\\(", "(a|b){0}"] {
+            assert!(check(&parse(pattern).unwrap()).is_ok(), "{}", pattern);
+        }
+        for pattern in ["\\q", "[a\\Q]", "(a|\\1)", "<b\\x>{0}"] {
+            assert!(check(&parse(pattern).unwrap()).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_generate_within_budget() {
+        assert_eq!(generate("a{3}", 3).unwrap(), "aaa");
+        assert_eq!(generate("<b>{3}", 3).unwrap(), "bbb");
+    }
+
+    #[test]
+    fn test_generate_exceeds_budget() {
+        for pattern in ["a{4}", "<b>{4}", "a{10000}", "<[a-z]{2}>{10000}"] {
+            let e = generate(pattern, 3).unwrap_err();
+            assert_eq!(e.downcast_ref::<TooLong>(), Some(&TooLong { limit: 3 }));
+        }
+    }
+
+    #[test]
+    fn test_generate_timeout() {
+        let timeout = Duration::from_millis(10);
+        let e = parse("((a{0}){10000}){10000}")
+            .unwrap()
+            .generate(
+                &mut rand::thread_rng(),
+                &mut Budget::new(3).with_timeout(timeout),
+            )
+            .unwrap_err();
+        assert_eq!(e.downcast_ref::<TimedOut>(), Some(&TimedOut { timeout }));
+    }
 }
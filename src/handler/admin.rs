@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use sqlx::MySqlPool;
+use traq_ws_bot::events::common::{EmbeddedInfo, User};
+
+use crate::model::db;
+
+use super::{mentioned_user, scope::Key, DATETIME_FORMAT};
+
+/// `/admin log` で表示する監査ログの数
+const AUDIT_LOG_SIZE: i64 = 20;
+
+/// 管理者だけが使えるコマンド
+#[derive(Debug, Clone)]
+pub enum AdminCommand {
+    /// key を削除し、作成者が復元できないようにロックする
+    Remove(Key),
+    Ban {
+        user: String,
+        reason: Option<String>,
+    },
+    Unban(String),
+    Lock(Key),
+    Unlock(Key),
+    Log,
+}
+
+/// `/admin` に続く引数を解釈する
+pub fn parse_admin_command(args: &[&str]) -> Result<AdminCommand> {
+    let (&action, args) = args.split_first().context("操作が必須です")?;
+    let target = || -> Result<&str> { args.first().copied().context("対象が必須です") };
+    match action {
+        "remove" | "delete" => {
+            anyhow::ensure!(args.len() <= 1, "key に空白を含めることはできません");
+            Ok(AdminCommand::Remove(Key::new(target()?)))
+        }
+        "ban" => {
+            let user = target()?.to_string();
+            let reason = (args.len() > 1).then(|| args[1..].join(" "));
+            Ok(AdminCommand::Ban { user, reason })
+        }
+        "unban" => Ok(AdminCommand::Unban(target()?.to_string())),
+        "lock" => {
+            anyhow::ensure!(args.len() <= 1, "key に空白を含めることはできません");
+            Ok(AdminCommand::Lock(Key::new(target()?)))
+        }
+        "unlock" => {
+            anyhow::ensure!(args.len() <= 1, "key に空白を含めることはできません");
+            Ok(AdminCommand::Unlock(Key::new(target()?)))
+        }
+        "log" | "audit" => Ok(AdminCommand::Log),
+        unknown => anyhow::bail!("不明な操作です: {}", unknown),
+    }
+}
+
+/// 管理者のコマンドを実行し、監査ログに記録して、返信する文章を返す
+pub async fn handle(
+    pool: &MySqlPool,
+    admin: &User,
+    embedded: &[EmbeddedInfo],
+    command: AdminCommand,
+) -> String {
+    let admin_id = &admin.id;
+    let admin_name = &admin.name;
+    let result = match &command {
+        AdminCommand::Remove(key) => remove(pool, key, admin_id, admin_name).await,
+        AdminCommand::Ban { user, reason } => match mentioned_user(embedded, user) {
+            Some(target) => {
+                let target_name = target.raw.trim_start_matches('@');
+                db::ban(pool, &target.id, target_name, reason.as_deref(), admin_id)
+                    .await
+                    .map(|banned| {
+                        if banned {
+                            format!("Banned: {}", target.raw)
+                        } else {
+                            format!("{} is already banned", target.raw)
+                        }
+                    })
+            }
+            None => return format!("User \"{}\" is not found", user),
+        },
+        AdminCommand::Unban(user) => match mentioned_user(embedded, user) {
+            Some(target) => db::unban(pool, &target.id).await.map(|unbanned| {
+                if unbanned {
+                    format!("Unbanned: {}", target.raw)
+                } else {
+                    format!("{} is not banned", target.raw)
+                }
+            }),
+            None => return format!("User \"{}\" is not found", user),
+        },
+        AdminCommand::Lock(key) => db::set_locked(pool, key, true).await.map(|found| {
+            if found {
+                format!("Locked: {}", key)
+            } else {
+                format!("Key \"{}\" is not found", key)
+            }
+        }),
+        AdminCommand::Unlock(key) => db::set_locked(pool, key, false).await.map(|found| {
+            if found {
+                format!("Unlocked: {}", key)
+            } else {
+                format!("Key \"{}\" is not found", key)
+            }
+        }),
+        AdminCommand::Log => {
+            return match db::audit_logs(pool, AUDIT_LOG_SIZE).await {
+                Ok(logs) => audit_log_text(&logs),
+                Err(e) => format!("Failed to get from database: {}", e),
+            }
+        }
+    };
+
+    match result {
+        Ok(text) => {
+            let (action, target, detail): (&str, &str, Option<&str>) = match &command {
+                AdminCommand::Remove(key) => ("remove", key, None),
+                AdminCommand::Ban { user, reason } => ("ban", user, reason.as_deref()),
+                AdminCommand::Unban(user) => ("unban", user, None),
+                AdminCommand::Lock(key) => ("lock", key, None),
+                AdminCommand::Unlock(key) => ("unlock", key, None),
+                AdminCommand::Log => unreachable!(),
+            };
+            let logged =
+                db::add_audit_log(pool, admin_id, admin_name, action, target, detail).await;
+            if let Err(e) = logged {
+                log::error!("Failed to add audit log: {:?}", e);
+            }
+            text
+        }
+        Err(e) => format!("Failed to run admin command: {}", e),
+    }
+}
+
+/// 削除した上でロックして、作成者が復元できないようにする
+/// すでに削除されていた場合も、復元できないようにロックする
+async fn remove(
+    pool: &MySqlPool,
+    key: &str,
+    admin_id: &str,
+    admin_name: &str,
+) -> sqlx::Result<String> {
+    let removed = db::remove(pool, key, admin_id, admin_name).await?;
+    let found = db::set_locked(pool, key, true).await?;
+    Ok(remove_text(key, removed, found))
+}
+
+fn remove_text(key: &str, removed: bool, found: bool) -> String {
+    match (removed, found) {
+        (true, _) => format!("Removed and locked: {}", key),
+        (false, true) => format!("Key \"{}\" is already removed, and now locked", key),
+        (false, false) => format!("Key \"{}\" is not found", key),
+    }
+}
+
+fn audit_log_text(logs: &[db::AuditLog]) -> String {
+    if logs.is_empty() {
+        return "監査ログはありません".to_string();
+    }
+
+    let mut lines = vec![
+        "| 日時 | 管理者 | 操作 | 対象 | 理由 |".to_string(),
+        "| --- | --- | --- | --- | --- |".to_string(),
+    ];
+    lines.extend(logs.iter().map(|l| {
+        format!(
+            "| {} | {} | {} | {} | {} |",
+            l.created_at.format(DATETIME_FORMAT),
+            l.user_name,
+            l.action,
+            l.target.replace('|', "\\|"),
+            l.detail.as_deref().unwrap_or("-").replace('|', "\\|")
+        )
+    }));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_admin_command() {
+        assert!(matches!(
+            parse_admin_command(&["ban", "@spammer", "too", "many", "saves"]),
+            Ok(AdminCommand::Ban { user, reason: Some(reason) })
+                if user == "@spammer" && reason == "too many saves"
+        ));
+        assert!(matches!(
+            parse_admin_command(&["lock", "traP"]),
+            Ok(AdminCommand::Lock(key)) if key == "traP"
+        ));
+        assert!(parse_admin_command(&[]).is_err());
+        assert!(parse_admin_command(&["remove"]).is_err());
+        assert!(parse_admin_command(&["promote", "@SSlime"]).is_err());
+    }
+
+    #[test]
+    fn test_remove_text() {
+        assert_eq!(remove_text("traP", true, true), "Removed and locked: traP");
+        assert_eq!(
+            remove_text("traP", false, true),
+            "Key \"traP\" is already removed, and now locked"
+        );
+        assert_eq!(
+            remove_text("traP", false, false),
+            "Key \"traP\" is not found"
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sqlx::MySqlPool;
+use traq_ws_bot::events::common::User;
+
+use crate::model::{
+    api::{download_file, upload_file},
+    db::{self, Conflict, Imported},
+    dump::{self, Format},
+    key,
+    store::PatternStore,
+};
+
+use super::{
+    permission::{self, Role},
+    scope::Scope,
+    strip_flag, validate_pattern, validate_tag,
+};
+
+/// `/import` で一度に取り込める正規表現の数
+const IMPORT_LIMIT: usize = 500;
+
+/// メッセージに添付されたファイルの URL
+static FILE_URL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"/files/([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})").unwrap()
+});
+
+/// メッセージ中のコードブロック
+static CODE_BLOCK_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)```[A-Za-z]*\n(.*?)```").unwrap());
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExportOptions {
+    /// 自分が保存したものだけを書き出す
+    pub mine: bool,
+    /// 自分の `~me/` の key も書き出す
+    /// チャンネルに公開されてしまうので、指定したときだけ含める
+    pub private: bool,
+    pub format: Format,
+}
+
+/// `/export` に続く引数を解釈する
+pub fn parse_export_options(args: &[&str]) -> Result<ExportOptions> {
+    let mut options = ExportOptions {
+        mine: false,
+        private: false,
+        format: Format::Json,
+    };
+    for &arg in args {
+        match arg {
+            "--mine" => options.mine = true,
+            "--private" => options.private = true,
+            "json" | "--json" => options.format = Format::Json,
+            "yaml" | "yml" | "--yaml" => options.format = Format::Yaml,
+            _ => anyhow::bail!("不明な引数です: {}", arg),
+        }
+    }
+    Ok(options)
+}
+
+/// 取り込む正規表現の書かれた場所
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportSource {
+    Text(String),
+    /// 添付されたファイルの ID
+    File(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub conflict: Conflict,
+    pub source: ImportSource,
+}
+
+/// `/import` に続く部分を解釈する
+/// 添付されたファイルがあればそれを、なければコードブロックか残りの部分を取り込む
+pub fn parse_import_options(mut rest: &str) -> Result<ImportOptions> {
+    let mut conflict = Conflict::default();
+    loop {
+        if let Some(r) = strip_flag(rest, "--skip") {
+            conflict = Conflict::Skip;
+            rest = r;
+        } else if let Some(r) = strip_flag(rest, "--overwrite") {
+            conflict = Conflict::Overwrite;
+            rest = r;
+        } else if let Some(r) = strip_flag(rest, "--rename") {
+            conflict = Conflict::Rename;
+            rest = r;
+        } else {
+            break;
+        }
+    }
+
+    let source = if let Some(captures) = FILE_URL_REGEX.captures(rest) {
+        ImportSource::File(captures[1].to_string())
+    } else if let Some(captures) = CODE_BLOCK_REGEX.captures(rest) {
+        ImportSource::Text(captures[1].to_string())
+    } else {
+        anyhow::ensure!(
+            !rest.is_empty(),
+            "取り込むファイルかコードブロックが必須です"
+        );
+        ImportSource::Text(rest.to_string())
+    };
+    Ok(ImportOptions { conflict, source })
+}
+
+/// このチャンネルから見える正規表現を書き出し、ファイルとしてアップロードする
+/// `--private` を指定しない限り、自分の `~me/` の key は含めない
+pub async fn export(
+    pool: &MySqlPool,
+    user_id: &str,
+    channel_id: &str,
+    scope: &Scope,
+    options: ExportOptions,
+) -> String {
+    let filter = db::ListFilter {
+        user_id: options.mine.then(|| user_id.to_string()),
+        visible_prefixes: if options.private {
+            scope.visible_prefixes()
+        } else {
+            vec![scope.channel_prefix()]
+        },
+        ..Default::default()
+    };
+    let patterns = match db::export(pool, &filter).await {
+        Ok(patterns) => patterns,
+        Err(e) => return format!("Failed to get from database: {}", e),
+    };
+    let count = patterns.len();
+    let content = match dump::to_string(patterns, options.format) {
+        Ok(content) => content,
+        Err(e) => return format!("Failed to export: {}", e),
+    };
+    let format = options.format;
+    match upload_file(channel_id, format.file_name(), format.mime_type(), content).await {
+        Ok(url) => format!("Exported {} patterns\n{}", count, url),
+        Err(e) => format!("Failed to upload: {}", e),
+    }
+}
+
+/// 正規表現を取り込み、結果を返信する文章を返す
+///
+/// key はこのユーザーとチャンネルから見た形として解決し、他のユーザーの key は取り込まない
+/// 上書きは、保存した人か共同管理者である key だけに行う
+pub async fn import(
+    pool: &MySqlPool,
+    store: &dyn PatternStore,
+    user: &User,
+    scope: &Scope,
+    options: ImportOptions,
+) -> String {
+    let text = match options.source {
+        ImportSource::Text(text) => text,
+        ImportSource::File(file_id) => match download_file(&file_id).await {
+            Ok(text) => text,
+            Err(e) => return format!("Failed to download: {}", e),
+        },
+    };
+    let patterns = match dump::parse(&text) {
+        Ok(patterns) => patterns,
+        Err(e) => return format!("Failed to import: {:#}", e),
+    };
+    if patterns.len() > IMPORT_LIMIT {
+        return format!(
+            "一度に取り込めるのは {} 件までです ({} 件)",
+            IMPORT_LIMIT,
+            patterns.len()
+        );
+    }
+
+    let mut report = Report::default();
+    for mut pattern in patterns {
+        let input = pattern.key.clone();
+        pattern.key = match scope.resolve(&input) {
+            Ok(key) => key,
+            Err(e) => {
+                report.failed.push((input, e));
+                continue;
+            }
+        };
+        if let Err(e) = key::validate(&pattern.key) {
+            report.failed.push((input, e));
+            continue;
+        }
+        if let Err(e) = validate_pattern(&pattern.regexp) {
+            report.failed.push((input, e));
+            continue;
+        }
+        if let Err(e) = pattern.tags.iter().try_for_each(|tag| validate_tag(tag)) {
+            report.failed.push((input, e));
+            continue;
+        }
+        let conflict = match options.conflict {
+            Conflict::Overwrite
+                if permission::check(store, &pattern.key, &user.id, Role::Maintainer)
+                    .await
+                    .is_err() =>
+            {
+                Conflict::Skip
+            }
+            conflict => conflict,
+        };
+        match db::import(pool, &pattern, conflict, &user.id, &user.name).await {
+            Ok(Imported::Saved) => report.saved += 1,
+            Ok(Imported::Overwritten) => report.overwritten += 1,
+            Ok(Imported::Renamed(renamed)) => report.renamed.push((input, renamed)),
+            Ok(Imported::Skipped) => report.skipped.push(input),
+            Err(e) => report.failed.push((input, e.to_string())),
+        }
+    }
+    report.text(scope)
+}
+
+/// 取り込んだ結果をまとめたもの
+#[derive(Debug, Default)]
+struct Report {
+    saved: usize,
+    overwritten: usize,
+    /// 元の key と、実際に保存した key
+    renamed: Vec<(String, String)>,
+    skipped: Vec<String>,
+    /// key と、失敗した理由
+    failed: Vec<(String, String)>,
+}
+
+impl Report {
+    fn text(&self, scope: &Scope) -> String {
+        let mut lines = vec![format!(
+            "Imported: {} saved, {} overwritten, {} renamed, {} skipped, {} failed",
+            self.saved,
+            self.overwritten,
+            self.renamed.len(),
+            self.skipped.len(),
+            self.failed.len()
+        )];
+        lines.extend(
+            self.renamed
+                .iter()
+                .map(|(key, renamed)| format!("- {} → {}", key, scope.display(renamed))),
+        );
+        if !self.skipped.is_empty() {
+            lines.push(format!("- スキップした key: {}", self.skipped.join(", ")));
+        }
+        lines.extend(
+            self.failed
+                .iter()
+                .map(|(key, reason)| format!("- {}: {}", key, reason)),
+        );
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_import_options() {
+        let options = parse_import_options(
+            "--rename https://q.trap.jp/files/0a0be82e-a9a1-4211-89c6-f7dbb0dced8c",
+        )
+        .unwrap();
+        assert_eq!(options.conflict, Conflict::Rename);
+        assert_eq!(
+            options.source,
+            ImportSource::File("0a0be82e-a9a1-4211-89c6-f7dbb0dced8c".to_string())
+        );
+
+        let options = parse_import_options("```yaml\n- key: traP\n  regexp: a\n```").unwrap();
+        assert_eq!(options.conflict, Conflict::Skip);
+        assert_eq!(
+            options.source,
+            ImportSource::Text("- key: traP\n  regexp: a\n".to_string())
+        );
+
+        assert!(parse_import_options("--overwrite").is_err());
+    }
+
+    #[test]
+    fn test_parse_export_options() {
+        let options = parse_export_options(&[]).unwrap();
+        assert!(!options.mine && !options.private);
+
+        let options = parse_export_options(&["--private", "yaml"]).unwrap();
+        assert!(options.private);
+        assert_eq!(options.format, Format::Yaml);
+
+        assert!(parse_export_options(&["--all"]).is_err());
+    }
+}
@@ -1,21 +1,41 @@
-use std::{convert::identity, sync::Arc};
+mod admin;
+mod backup;
+mod permission;
+mod scope;
 
-use anyhow::Result;
+use std::{convert::identity, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
 use once_cell::sync::Lazy;
 use regex::Regex;
+use sqlx::{types::chrono::NaiveDateTime, MySqlPool};
 use traq_ws_bot::{
-    events::{common::Message, payload},
+    events::{
+        common::{EmbeddedInfo, Message, User},
+        payload,
+    },
     utils::is_mentioned_message,
 };
 
 use crate::{
-    config::{Resource, BOT_NAME, BOT_USER_ID},
-    generator::Generate,
+    config::{Resource, BOT_NAME, BOT_USER_ID, GENERATION_TIMEOUT, TOMBSTONE_RETENTION_DAYS},
+    explainer::Explain,
+    generator::{self, Budget, Generate, TimedOut, TooLong},
+    matcher,
     model::{
+        self,
         api::{join_channel, leave_channel, send_message},
         db,
     },
     parser,
+    stats::{self, Analyze, Deadline},
+};
+
+use self::{
+    admin::{parse_admin_command, AdminCommand},
+    backup::{parse_export_options, parse_import_options, ExportOptions, ImportOptions},
+    permission::Role,
+    scope::{Key, Scope},
 };
 
 /// like !{\"type\":\"user\",\"raw\":\"@BOT_STimer\",\"id\":\"d352688f-a656-4444-8c5f-caa517e9ea1b\"}
@@ -29,23 +49,434 @@ static MENTION_REGEX: Lazy<String> = Lazy::new(|| {
 const SPECIAL_MESSAGE_REGEX: &str =
     r#"!\{"type":"(user|channel|group)","raw":"(?P<raw>(?:[^\\"]|\\.)+)","id":"(?:[^\\"]|\\.)+"\}"#;
 
+/// 生成する文字列の最大の文字数
 const LENGTH_LIMIT: usize = 3000;
 
+/// `/list` で 1 ページに表示する件数
+const LIST_PAGE_SIZE: usize = 20;
+
+/// `/list` で表示する正規表現の最大の文字数
+const LIST_PATTERN_LENGTH: usize = 30;
+
+/// `--desc` でつける説明の最大の文字数
+const DESCRIPTION_LENGTH_LIMIT: usize = 200;
+
+/// `--tag` でつけるタグの最大の文字数
+const TAG_LENGTH_LIMIT: usize = 32;
+
+/// `/stats` で表示する、よく生成される文字列の数
+const TOP_OUTPUTS_COUNT: usize = 5;
+
+/// `/stats` で、よく生成される文字列を求めるときに列挙する文字列の最大数
+const OUTCOMES_LIMIT: usize = 10000;
+
+/// 最短でも `LENGTH_LIMIT` を超える場合は、生成しなくても失敗するとわかる
+fn check_min_length(parsed: &model::Expression) -> Result<(), String> {
+    let min_length = parsed.min_length();
+    if min_length > LENGTH_LIMIT {
+        return Err(format!(
+            "Generated text is always too long: at least {} > {} characters",
+            min_length, LENGTH_LIMIT
+        ));
+    }
+    Ok(())
+}
+
 async fn generate_text(regexp: &str) -> Result<String, String> {
-    let parsed = parser::parse(regexp).map_err(|e| format!("Failed to parse: {:?}", e))?;
-    let mut rng = rand::thread_rng();
-    let text = parsed
-        .generate(&mut rng)
-        .map_err(|e| format!("Failed to generate: {:?}", e))?;
-    if text.len() > LENGTH_LIMIT {
-        Err(format!(
-            "Generated text is too long: {} > {}",
-            text.len(),
-            LENGTH_LIMIT
-        ))
+    let parsed = parser::parse(regexp).map_err(|e| format!("Failed to parse: {}", e))?;
+    check_min_length(&parsed)?;
+
+    run_blocking(*GENERATION_TIMEOUT, move || {
+        let mut rng = rand::thread_rng();
+        let mut budget = Budget::new(LENGTH_LIMIT).with_timeout(*GENERATION_TIMEOUT);
+        parsed.generate(&mut rng, &mut budget).map_err(|e| {
+            if e.is::<TooLong>() || e.is::<TimedOut>() {
+                e.to_string()
+            } else {
+                format!("Failed to generate: {:?}", e)
+            }
+        })
+    })
+    .await
+}
+
+/// 生成や確率の計算など CPU を専有する処理を、他のメッセージの処理を止めないように別スレッドで行う
+/// `timeout` を過ぎても終わらない場合は、結果を待たずに打ち切ったことを返す
+async fn run_blocking<F>(timeout: Duration, f: F) -> Result<String, String>
+where
+    F: FnOnce() -> Result<String, String> + Send + 'static,
+{
+    match tokio::time::timeout(timeout, tokio::task::spawn_blocking(f)).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err(format!("Failed to compute: {:?}", e)),
+        Err(_) => Err(format!(
+            "Timed out: took more than {} ms",
+            timeout.as_millis()
+        )),
+    }
+}
+
+/// `--tag` や取り込むファイルでつけるタグが、空でなく長すぎないかを確かめる
+pub fn validate_tag(tag: &str) -> Result<(), String> {
+    if tag.is_empty() {
+        return Err("空のタグはつけられません".to_string());
+    }
+    if tag.chars().count() > TAG_LENGTH_LIMIT {
+        return Err(format!(
+            "タグは {} 文字以内にしてください",
+            TAG_LENGTH_LIMIT
+        ));
+    }
+    Ok(())
+}
+
+/// 解釈に失敗した位置がわかる場合は、その位置を `^` で示す
+fn parse_error_text(regexp: &str, e: &parser::ParseError) -> String {
+    match *e {
+        parser::ParseError::Syntax { position } => format!(
+            "Failed to parse: {}\n```\n{}\n{}^\n```",
+            e,
+            regexp,
+            " ".repeat(position)
+        ),
+        _ => format!("Failed to parse: {}", e),
+    }
+}
+
+/// 保存する前に、正規表現が解釈でき、生成すると必ず失敗する部分がないかを確かめる
+///
+/// 乱数で生成してみると結果が毎回変わるので、構文木を調べるだけにする
+pub fn validate_pattern(regexp: &str) -> Result<(), String> {
+    let parsed = parser::parse(regexp).map_err(|e| parse_error_text(regexp, &e))?;
+    check_min_length(&parsed)?;
+    generator::check(&parsed).map_err(|e| format!("Failed to generate: {}", e))
+}
+
+fn format_probability(p: f64) -> String {
+    if p == 0.0 {
+        "0".to_string()
+    } else if p >= 1e-4 {
+        format!("{:.4}%", p * 100.0)
+    } else {
+        format!("{:.4e}", p)
+    }
+}
+
+fn format_output(s: &str) -> String {
+    if s.is_empty() {
+        "(空文字列)".to_string()
+    } else {
+        format!("`{}`", s)
+    }
+}
+
+fn probability_text(regexp: &str, text: &str) -> Result<String, String> {
+    let parsed = parser::parse(regexp).map_err(|e| format!("Failed to parse: {}", e))?;
+    Ok(format!(
+        "{} が生成される確率: {}",
+        format_output(text),
+        format_probability(
+            stats::probability(&parsed, text, &Deadline::after(*GENERATION_TIMEOUT))
+                .map_err(|e| e.to_string())?
+        )
+    ))
+}
+
+fn stats_text(regexp: &str) -> Result<String, String> {
+    let parsed = parser::parse(regexp).map_err(|e| format!("Failed to parse: {}", e))?;
+    let max_length = parsed
+        .max_length()
+        .map_or_else(|| "上限なし".to_string(), |m| format!("{} 文字", m));
+    let mut lines = vec![
+        format!("期待される長さ: {:.4} 文字", parsed.expected_length()),
+        format!("最短の長さ: {} 文字", parsed.min_length()),
+        format!("最長の長さ: {}", max_length),
+    ];
+    let deadline = Deadline::after(*GENERATION_TIMEOUT);
+    match stats::top_outputs(&parsed, TOP_OUTPUTS_COUNT, OUTCOMES_LIMIT, &deadline)
+        .map_err(|e| e.to_string())?
+    {
+        Some(top) => {
+            lines.push("よく生成される文字列:".to_string());
+            lines.extend(top.iter().enumerate().map(|(i, (s, p))| {
+                format!(
+                    "{}. {} ({})",
+                    i + 1,
+                    format_output(s),
+                    format_probability(*p)
+                )
+            }));
+        }
+        None => lines.push(
+            "生成されうる文字列が多すぎるため、よく生成される文字列は表示できません".to_string(),
+        ),
+    }
+    Ok(lines.join("\n"))
+}
+
+fn match_text(regexp: &str, text: &str) -> Result<String, String> {
+    let parsed = parser::parse(regexp).map_err(|e| format!("Failed to parse: {}", e))?;
+    if !matcher::is_match(&parsed, text) {
+        return Ok(format!("{} は生成されません", format_output(text)));
+    }
+
+    let mut lines = vec![format!("{} は生成されえます", format_output(text))];
+    let model::Expression::Union(terms) = &parsed;
+    if terms.len() > 1 {
+        lines.push("生成しうる選択肢:".to_string());
+        lines.extend(
+            matcher::matching_branches(&parsed, text)
+                .into_iter()
+                .map(|i| format!("{}. `{}`", i + 1, terms[i])),
+        );
+    }
+    Ok(lines.join("\n"))
+}
+
+/// `max` 文字を超える場合は切り詰めて `…` をつける
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() > max {
+        format!("{}…", s.chars().take(max).collect::<String>())
     } else {
-        Ok(text)
+        s.to_string()
+    }
+}
+
+/// markdown のインラインコードとして表示できるようにする
+fn code_span(s: &str) -> String {
+    let fence = if s.contains('`') { "``" } else { "`" };
+    format!("{fence} {s} {fence}")
+}
+
+/// key と作成者、正規表現の表
+fn entries_table(entries: &[db::Entry], scope: &Scope) -> Vec<String> {
+    let mut lines = vec![
+        "| key | 作成者 | 正規表現 |".to_string(),
+        "| --- | --- | --- |".to_string(),
+    ];
+    lines.extend(entries.iter().map(|e| {
+        format!(
+            "| {} | {} | {} |",
+            scope.display(&e.key).replace('|', "\\|"),
+            e.user_name,
+            code_span(&truncate(&e.regexp, LIST_PATTERN_LENGTH)).replace('|', "\\|")
+        )
+    }));
+    lines
+}
+
+/// ページ番号から、読み飛ばす件数を求める
+/// 大きすぎて表せない場合は、返信する文章を返す
+fn page_offset(page: usize) -> Result<i64, String> {
+    page.checked_sub(1)
+        .and_then(|p| p.checked_mul(LIST_PAGE_SIZE))
+        .and_then(|offset| i64::try_from(offset).ok())
+        .ok_or_else(|| format!("{} ページ目はありません", page))
+}
+
+fn list_text(page: &db::Page, page_number: usize, scope: &Scope) -> String {
+    if page.entries.is_empty() {
+        return if page.total == 0 {
+            "保存された正規表現はありません".to_string()
+        } else {
+            format!("{} ページ目はありません", page_number)
+        };
+    }
+
+    let mut lines = entries_table(&page.entries, scope);
+    let pages = (page.total as usize).div_ceil(LIST_PAGE_SIZE);
+    lines.push(String::new());
+    lines.push(format!(
+        "{} / {} ページ (全 {} 件)",
+        page_number, pages, page.total
+    ));
+    lines.join("\n")
+}
+
+fn search_text(entries: &[db::Entry], scope: &Scope) -> String {
+    if entries.is_empty() {
+        return "一致する正規表現はありません".to_string();
+    }
+    entries_table(entries, scope).join("\n")
+}
+
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// `/history` で表示する履歴の数
+const HISTORY_SIZE: usize = 20;
+
+fn action_label(action: &str) -> &str {
+    match action {
+        "save" => "保存",
+        "update" => "更新",
+        "delete" => "削除",
+        "restore" => "復元",
+        "rollback" => "ロールバック",
+        _ => action,
+    }
+}
+
+fn history_text(revisions: &[db::Revision]) -> String {
+    let mut lines = vec![
+        "| rev | 操作 | 作成者 | 日時 | 正規表現 |".to_string(),
+        "| --- | --- | --- | --- | --- |".to_string(),
+    ];
+    lines.extend(revisions.iter().map(|r| {
+        format!(
+            "| {} | {} | {} | {} | {} |",
+            r.revision,
+            action_label(&r.action),
+            r.user_name,
+            r.created_at.format(DATETIME_FORMAT),
+            code_span(&truncate(&r.regexp, LIST_PATTERN_LENGTH)).replace('|', "\\|")
+        )
+    }));
+    lines.join("\n")
+}
+
+/// `/lucky` で、生成できない正規表現を選んだときに選び直す回数
+const LUCKY_ATTEMPTS: usize = 5;
+
+/// `/ranking` や `/mystats` で表示する順位の数
+const RANKING_SIZE: usize = 10;
+
+/// 呼び出された回数の順位表
+fn usage_table(
+    column: &str,
+    usages: &[db::Usage],
+    display: impl Fn(&str) -> String,
+) -> Vec<String> {
+    let mut lines = vec![
+        format!("| 順位 | {} | 回数 |", column),
+        "| --- | --- | --- |".to_string(),
+    ];
+    lines.extend(usages.iter().enumerate().map(|(i, u)| {
+        format!(
+            "| {} | {} | {} |",
+            i + 1,
+            display(&u.name).replace('|', "\\|"),
+            u.count
+        )
+    }));
+    lines
+}
+
+fn ranking_text(period: Period, keys: &[db::Usage], users: &[db::Usage], scope: &Scope) -> String {
+    if keys.is_empty() && users.is_empty() {
+        return format!("{}に呼び出された正規表現はありません", period.label());
+    }
+
+    let mut lines = vec![format!("#### {}によく呼び出された key", period.label())];
+    lines.extend(usage_table("key", keys, |k| scope.display(k)));
+    lines.push(String::new());
+    lines.push(format!("#### {}によく呼び出した人", period.label()));
+    lines.extend(usage_table("ユーザー", users, str::to_string));
+    lines.join("\n")
+}
+
+fn my_stats_text(calls: i64, owned: &db::OwnerStats, keys: &[db::Usage], scope: &Scope) -> String {
+    let mut lines = vec![
+        format!("呼び出した回数: {}", calls),
+        format!("保存している key: {}", owned.keys),
+        format!("保存している key が呼び出された回数: {}", owned.calls),
+    ];
+    if !keys.is_empty() {
+        lines.push(String::new());
+        lines.push("#### よく呼び出す key".to_string());
+        lines.extend(usage_table("key", keys, |k| scope.display(k)));
+    }
+    lines.join("\n")
+}
+
+/// 保存する前の確認に失敗したときに、`--force` の使い方を案内する
+fn force_hint(command: &str, key: &Key) -> String {
+    format!(
+        "下書きとしてそのまま保存する場合は `/{} --force {} {{正規表現}}` としてください",
+        command, key
+    )
+}
+
+/// `/save` や `/update` で、`--force` がなければ正規表現を確かめる
+/// 失敗した場合は、理由と `--force` の使い方を返す
+fn check_pattern(command: &str, key: &Key, value: &str, force: bool) -> Result<(), String> {
+    if force {
+        return Ok(());
     }
+    validate_pattern(value).map_err(|e| format!("{}\n{}", e, force_hint(command, key)))
+}
+
+/// 削除されて、まだ復元できる key を保存しようとしたときの文章
+fn removed_key_text(key: &Key, deleted_at: NaiveDateTime) -> String {
+    format!(
+        "Key \"{}\" was removed at {} and is kept for {} days so that it can be restored",
+        key,
+        deleted_at.format(DATETIME_FORMAT),
+        *TOMBSTONE_RETENTION_DAYS
+    )
+}
+
+fn format_size(size: stats::Size) -> String {
+    match size {
+        stats::Size::Finite(n) => format!("最大 {} 通り", n),
+        stats::Size::TooMany => format!("{} 通り以上", u128::MAX),
+        stats::Size::Infinite => "無限".to_string(),
+    }
+}
+
+fn info_text(
+    info: &db::Info,
+    tags: &[String],
+    maintainers: &[String],
+    aliases: &[String],
+    scope: &Scope,
+) -> String {
+    let size = match parser::parse(&info.regexp) {
+        Ok(parsed) => format_size(parsed.size()),
+        Err(e) => format!("(Failed to parse: {})", e),
+    };
+    let updated_at = info.updated_at.map_or_else(
+        || "-".to_string(),
+        |t| t.format(DATETIME_FORMAT).to_string(),
+    );
+    [
+        format!("key: {}", scope.display(&info.key)),
+        format!("正規表現: {}", code_span(&info.regexp)),
+        format!("説明: {}", info.description.as_deref().unwrap_or("-")),
+        format!(
+            "タグ: {}",
+            if tags.is_empty() {
+                "-".to_string()
+            } else {
+                tags.join(", ")
+            }
+        ),
+        format!(
+            "別名: {}",
+            if aliases.is_empty() {
+                "-".to_string()
+            } else {
+                aliases
+                    .iter()
+                    .map(|a| scope.display(a))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        ),
+        format!("作成者: {}", info.user_name),
+        format!(
+            "共同管理者: {}",
+            if maintainers.is_empty() {
+                "-".to_string()
+            } else {
+                maintainers.join(", ")
+            }
+        ),
+        format!("作成日時: {}", info.created_at.format(DATETIME_FORMAT)),
+        format!("最終更新: {}", updated_at),
+        format!("呼び出し回数: {}", info.call_count),
+        format!("生成されうる文字列: {}", size),
+    ]
+    .join("\n")
 }
 
 async fn message_like_handler(message: Message, resource: Arc<Resource>) {
@@ -70,7 +501,7 @@ async fn message_like_handler(message: Message, resource: Arc<Resource>) {
         .to_string();
     log::debug!("Parsed message: {:?}", content);
 
-    let command = match parse_command(&content) {
+    let mut command = match parse_command(&content) {
         Ok(command) => command,
         Err(e) => {
             {
@@ -98,6 +529,23 @@ async fn message_like_handler(message: Message, resource: Arc<Resource>) {
         }
     };
 
+    let scope = Scope::new(&message.user.id, &message.channel_id, &message.embedded);
+    if let Err(text) = command.resolve_keys(&scope) {
+        let res = send_message(&message.channel_id, &text, true).await;
+        if let Err(e) = res {
+            log::error!("Failed to send message: {:?}", e);
+        }
+        return;
+    }
+
+    if let Some(text) = deny_reason(&command, &message.user.id, &resource).await {
+        let res = send_message(&message.channel_id, &text, true).await;
+        if let Err(e) = res {
+            log::error!("Failed to send message: {:?}", e);
+        }
+        return;
+    }
+
     match command {
         Command::RandRegexp(regexp) => {
             let text = generate_text(&regexp).await.unwrap_or_else(identity);
@@ -106,25 +554,126 @@ async fn message_like_handler(message: Message, resource: Arc<Resource>) {
                 log::error!("Failed to send message: {:?}", e);
             }
         }
-        Command::Save { key, value } => {
-            let user_id = message.user.id;
-            let user_name = message.user.name;
-            let pool = resource.clone();
-            let result = db::save(&pool, &key, &value, &user_id, &user_name).await;
+        Command::Explain(regexp) => {
+            let text = match parser::parse(&regexp) {
+                Ok(parsed) => parsed.explain().render(),
+                Err(e) => format!("Failed to parse: {}", e),
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Probability { regexp, text } => {
+            let text = run_blocking(*GENERATION_TIMEOUT, move || {
+                probability_text(&regexp, &text)
+            })
+            .await
+            .unwrap_or_else(identity);
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Stats(regexp) => {
+            let text = run_blocking(*GENERATION_TIMEOUT, move || stats_text(&regexp))
+                .await
+                .unwrap_or_else(identity);
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Match { target, text } => {
+            let candidates = scope.lookup(&target).unwrap_or_default();
+            let result = resource.store.find(&candidates).await;
             let text = match result {
-                Ok(_) => {
-                    format!("Saved: {} => {}", key, value)
+                Ok(found) => {
+                    let regexp = found.map_or(target, |(_, value)| value);
+                    run_blocking(*GENERATION_TIMEOUT, move || match_text(&regexp, &text))
+                        .await
+                        .unwrap_or_else(identity)
                 }
                 Err(e) => {
-                    // if key duplicated error (code: 1062)
-                    if e.as_database_error().map_or(false, |e| {
-                        e.code().map(|x| x.to_string()) == Some("1062".to_string())
-                    }) {
-                        format!("Key \"{}\" is already exists", key)
-                    } else {
-                        format!("Failed to save: {}", e)
+                    format!("Failed to get from database: {}", e)
+                }
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Save {
+            key,
+            value,
+            options,
+        } => {
+            let text = match check_pattern("save", &key, &value, options.force) {
+                Ok(()) => {
+                    save_pattern(
+                        &resource,
+                        &key,
+                        &value,
+                        &options.metadata,
+                        &message.user.id,
+                        &message.user.name,
+                        &scope,
+                    )
+                    .await
+                }
+                Err(e) => e,
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Update {
+            key,
+            value,
+            options,
+        } => {
+            let text = match check_pattern("update", &key, &value, options.force) {
+                Ok(()) => {
+                    let user_id = message.user.id;
+                    let user_name = message.user.name;
+                    let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                        return;
+                    };
+                    match permission::check(
+                        resource.store.as_ref(),
+                        &key,
+                        &user_id,
+                        Role::Maintainer,
+                    )
+                    .await
+                    {
+                        Ok(()) => {
+                            let result = db::update(
+                                &pool,
+                                &key,
+                                &value,
+                                &options.metadata,
+                                &user_id,
+                                &user_name,
+                            )
+                            .await;
+                            match result {
+                                Ok(true) => {
+                                    format!("Updated: {} => {}", key, value)
+                                }
+                                Ok(false) => {
+                                    format!("Key \"{}\" is not found", key)
+                                }
+                                Err(e) => {
+                                    format!("Failed to update: {}", e)
+                                }
+                            }
+                        }
+                        Err(e) => e,
                     }
                 }
+                Err(e) => e,
             };
             let res = send_message(&message.channel_id, &text, true).await;
             if let Err(e) = res {
@@ -132,10 +681,17 @@ async fn message_like_handler(message: Message, resource: Arc<Resource>) {
             }
         }
         Command::Call(key) => {
-            let pool = resource.clone();
-            let result = db::get(&pool, &key).await;
+            let result = resource.store.find(key.lookup()).await;
             let text = match result {
-                Ok(Some(value)) => generate_text(&value).await.unwrap_or_else(identity),
+                Ok(Some((found, value))) => match generate_text(&value).await {
+                    Ok(text) => {
+                        if let Some(pool) = &resource.pool {
+                            record_call(pool, &found, &message.user, &message.channel_id).await;
+                        }
+                        text
+                    }
+                    Err(e) => e,
+                },
                 Ok(None) => {
                     format!("Key \"{}\" is not found", key)
                 }
@@ -148,49 +704,630 @@ async fn message_like_handler(message: Message, resource: Arc<Resource>) {
                 log::error!("Failed to send message: {:?}", e);
             }
         }
-        Command::Remove(key) => {
-            let pool = resource.clone();
-            let user_id = message.user.id;
-            let result = db::remove(&pool, &key, &user_id).await;
+        Command::Lucky {
+            owner,
+            tag,
+            prefer_favorites,
+        } => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let filter = list_filter(owner, None, tag, &message.user.id, &scope);
+            let mut result = Ok(None);
+            if prefer_favorites {
+                let favorites = db::ListFilter {
+                    favorite_of: Some(message.user.id.clone()),
+                    ..filter.clone()
+                };
+                result = generate_lucky(&pool, &favorites).await;
+            }
+            // お気に入りがない場合は、すべての正規表現から選ぶ
+            if matches!(result, Ok(None)) {
+                result = generate_lucky(&pool, &filter).await;
+            }
+            let text = match result {
+                Ok(Some((key, text))) => {
+                    record_call(&pool, &key, &message.user, &message.channel_id).await;
+                    format!("{}\n(key: {})", text, scope.display(&key))
+                }
+                Ok(None) => "生成できる正規表現が見つかりませんでした".to_string(),
+                Err(e) => {
+                    format!("Failed to get from database: {}", e)
+                }
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Fav(key) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let result = match db::find_key(&pool, key.lookup()).await {
+                Ok(Some(found)) => db::add_favorite(&pool, &message.user.id, &found)
+                    .await
+                    .map(Some),
+                Ok(None) => Ok(None),
+                Err(e) => Err(e),
+            };
+            let text = match result {
+                Ok(Some(true)) => {
+                    format!("Added to favorites: {}", key)
+                }
+                Ok(Some(false)) => {
+                    format!("Key \"{}\" is already in your favorites", key)
+                }
+                Ok(None) => {
+                    format!("Key \"{}\" is not found", key)
+                }
+                Err(e) => {
+                    format!("Failed to add to favorites: {}", e)
+                }
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Unfav(key) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            // 削除された key もお気に入りから外せるように、見つからない場合は入力された key を使う
+            let result = match db::find_key(&pool, key.lookup()).await {
+                Ok(found) => {
+                    let found = found.unwrap_or_else(|| key.to_string());
+                    db::remove_favorite(&pool, &message.user.id, &found).await
+                }
+                Err(e) => Err(e),
+            };
             let text = match result {
                 Ok(true) => {
-                    format!("Removed: {}", key)
+                    format!("Removed from favorites: {}", key)
                 }
                 Ok(false) => {
+                    format!("Key \"{}\" is not in your favorites", key)
+                }
+                Err(e) => {
+                    format!("Failed to remove from favorites: {}", e)
+                }
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Favs(page) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let filter = db::ListFilter {
+                favorite_of: Some(message.user.id.clone()),
+                visible_prefixes: scope.visible_prefixes(),
+                ..Default::default()
+            };
+            let text = match page_offset(page) {
+                Ok(offset) => match db::list(&pool, &filter, LIST_PAGE_SIZE as i64, offset).await {
+                    Ok(entries) if entries.total == 0 => "お気に入りはありません".to_string(),
+                    Ok(entries) => list_text(&entries, page, &scope),
+                    Err(e) => {
+                        format!("Failed to get from database: {}", e)
+                    }
+                },
+                Err(e) => e,
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Info(key) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let result = match db::find_key(&pool, key.lookup()).await {
+                Ok(Some(found)) => db::get_info(&pool, &found).await,
+                Ok(None) => Ok(None),
+                Err(e) => Err(e),
+            };
+            let text = match result {
+                Ok(Some(info)) => match tokio::try_join!(
+                    db::tags(&pool, &info.key),
+                    db::maintainers(&pool, &info.key),
+                    db::aliases(&pool, &info.key)
+                ) {
+                    Ok((tags, maintainers, aliases)) => {
+                        info_text(&info, &tags, &maintainers, &aliases, &scope)
+                    }
+                    Err(e) => {
+                        format!("Failed to get from database: {}", e)
+                    }
+                },
+                Ok(None) => {
                     format!("Key \"{}\" is not found", key)
                 }
                 Err(e) => {
-                    format!("Failed to remove: {}", e)
+                    format!("Failed to get from database: {}", e)
                 }
             };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Remove(key) => {
+            let user_id = message.user.id;
+            let user_name = message.user.name;
+            let allowed =
+                permission::check(resource.store.as_ref(), &key, &user_id, Role::Maintainer).await;
+            let text = match allowed {
+                Ok(()) => match resource.store.remove(&key, &user_id, &user_name).await {
+                    // MariaDB 以外では削除を記録しないので、`/restore` で戻せない
+                    Ok(true) if resource.pool.is_none() => {
+                        format!(
+                            "Removed: {}\nこの保存先では、削除した key は復元できません",
+                            key
+                        )
+                    }
+                    Ok(true) => {
+                        format!("Removed: {}", key)
+                    }
+                    Ok(false) => {
+                        format!("Key \"{}\" is not found", key)
+                    }
+                    Err(e) => {
+                        format!("Failed to remove: {}", e)
+                    }
+                },
+                Err(e) => e,
+            };
 
             let res = send_message(&message.channel_id, &text, true).await;
             if let Err(e) = res {
                 log::error!("Failed to send message: {:?}", e);
             }
         }
-        Command::Join => {
-            let res = join_channel(&message.channel_id).await;
+        Command::Restore(key) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let user_id = message.user.id;
+            let user_name = message.user.name;
+            let text = match permission::check(resource.store.as_ref(), &key, &user_id, Role::Owner)
+                .await
+            {
+                Ok(()) => match db::restore(&pool, &key, &user_id, &user_name).await {
+                    Ok(true) => {
+                        format!("Restored: {}", key)
+                    }
+                    Ok(false) => {
+                        format!("Removed key \"{}\" is not found", key)
+                    }
+                    Err(e) => {
+                        format!("Failed to restore: {}", e)
+                    }
+                },
+                Err(e) => e,
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
             if let Err(e) = res {
-                log::error!("Failed to join channel: {:?}", e);
+                log::error!("Failed to send message: {:?}", e);
             }
         }
-        Command::Leave => {
-            let res = leave_channel(&message.channel_id).await;
+        Command::Transfer { key, user } => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let target = mentioned_user(&message.embedded, &user).cloned();
+            let user_id = message.user.id;
+            let text = match target {
+                Some(target) => {
+                    match permission::check(resource.store.as_ref(), &key, &user_id, Role::Owner)
+                        .await
+                    {
+                        Ok(()) => {
+                            let target_name = target.raw.trim_start_matches('@');
+                            match db::transfer(&pool, &key, &target.id, target_name).await {
+                                Ok(true) => {
+                                    format!("Transferred: {} => {}", key, target.raw)
+                                }
+                                Ok(false) => {
+                                    format!("Key \"{}\" is not found", key)
+                                }
+                                Err(e) => {
+                                    format!("Failed to transfer: {}", e)
+                                }
+                            }
+                        }
+                        Err(e) => e,
+                    }
+                }
+                None => format!("User \"{}\" is not found", user),
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
             if let Err(e) = res {
-                log::error!("Failed to leave channel: {:?}", e);
+                log::error!("Failed to send message: {:?}", e);
             }
         }
-        Command::Help => {
-            let text = "[README](https://github.com/SSlime-s/BOT_SRegexp/blob/main/README.md)";
-            let res = send_message(&message.channel_id, text, true).await;
+        Command::Maintainer { action, key, user } => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let target = mentioned_user(&message.embedded, &user).cloned();
+            let user_id = message.user.id;
+            let text = match target {
+                Some(target) => {
+                    match permission::check(resource.store.as_ref(), &key, &user_id, Role::Owner)
+                        .await
+                    {
+                        Ok(()) => {
+                            let target_name = target.raw.trim_start_matches('@');
+                            let result = match action {
+                                MaintainerAction::Add => {
+                                    db::add_maintainer(&pool, &key, &target.id, target_name).await
+                                }
+                                MaintainerAction::Remove => {
+                                    db::remove_maintainer(&pool, &key, &target.id).await
+                                }
+                            };
+                            match (action, result) {
+                                (MaintainerAction::Add, Ok(true)) => {
+                                    format!("Added maintainer of {}: {}", key, target.raw)
+                                }
+                                (MaintainerAction::Add, Ok(false)) => {
+                                    format!("{} is already a maintainer of {}", target.raw, key)
+                                }
+                                (MaintainerAction::Remove, Ok(true)) => {
+                                    format!("Removed maintainer of {}: {}", key, target.raw)
+                                }
+                                (MaintainerAction::Remove, Ok(false)) => {
+                                    format!("{} is not a maintainer of {}", target.raw, key)
+                                }
+                                (_, Err(e)) => {
+                                    format!("Failed to update maintainers: {}", e)
+                                }
+                            }
+                        }
+                        Err(e) => e,
+                    }
+                }
+                None => format!("User \"{}\" is not found", user),
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
             if let Err(e) = res {
                 log::error!("Failed to send message: {:?}", e);
             }
         }
-    }
-}
-
+        Command::Alias { alias, key } => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let user_id = message.user.id;
+            let user_name = message.user.name;
+            let text = match tokio::try_join!(
+                db::get_owner(&pool, &alias),
+                db::get_alias(&pool, &alias),
+                db::find_key(&pool, key.lookup())
+            ) {
+                Ok((Some(_), _, _)) | Ok((_, Some(_), _)) => {
+                    format!("Key \"{}\" is already exists", alias)
+                }
+                Ok((None, None, None)) => {
+                    format!("Key \"{}\" is not found", key)
+                }
+                Ok((None, None, Some(found))) if !model::key::same_scope(&alias, &found) => {
+                    alias_scope_error(&alias, &found, &scope)
+                }
+                Ok((None, None, Some(found))) => {
+                    match db::add_alias(&pool, &alias, &found, &user_id, &user_name).await {
+                        Ok(true) => {
+                            format!("Aliased: {} => {}", alias, scope.display(&found))
+                        }
+                        Ok(false) => {
+                            format!("Key \"{}\" is already exists", alias)
+                        }
+                        Err(e) => {
+                            format!("Failed to save: {}", e)
+                        }
+                    }
+                }
+                Err(e) => {
+                    format!("Failed to get from database: {}", e)
+                }
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Unalias(alias) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let user_id = message.user.id;
+            let text = match db::get_alias(&pool, &alias).await {
+                Ok(Some(found)) => {
+                    // 別名をつけた人か、別名をつけられた key を管理している人が消せる
+                    let allowed = if found.user_id == user_id {
+                        Ok(())
+                    } else {
+                        permission::check(
+                            resource.store.as_ref(),
+                            &found.key,
+                            &user_id,
+                            Role::Maintainer,
+                        )
+                        .await
+                    };
+                    match allowed {
+                        Ok(()) => match db::remove_alias(&pool, &alias).await {
+                            Ok(_) => {
+                                format!("Removed alias: {}", alias)
+                            }
+                            Err(e) => {
+                                format!("Failed to remove: {}", e)
+                            }
+                        },
+                        Err(e) => e,
+                    }
+                }
+                Ok(None) => {
+                    format!("Alias \"{}\" is not found", alias)
+                }
+                Err(e) => {
+                    format!("Failed to get from database: {}", e)
+                }
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::History(key) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let result = db::history(&pool, &key, HISTORY_SIZE as i64).await;
+            let text = match result {
+                Ok(revisions) if revisions.is_empty() => {
+                    format!("Key \"{}\" is not found", key)
+                }
+                Ok(revisions) => history_text(&revisions),
+                Err(e) => {
+                    format!("Failed to get from database: {}", e)
+                }
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Rollback { key, revision } => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let user_id = message.user.id;
+            let user_name = message.user.name;
+            let text =
+                match permission::check(resource.store.as_ref(), &key, &user_id, Role::Maintainer)
+                    .await
+                {
+                    Ok(()) => match db::get_revision(&pool, &key, revision).await {
+                        Ok(Some(revision)) => {
+                            match db::rollback(&pool, &key, &revision, &user_id, &user_name).await {
+                                Ok(()) => format!(
+                                    "Rolled back: {} => {} (rev {})",
+                                    key, revision.regexp, revision.revision
+                                ),
+                                Err(e) => {
+                                    format!("Failed to roll back: {}", e)
+                                }
+                            }
+                        }
+                        Ok(None) => {
+                            format!("Revision {} of \"{}\" is not found", revision, key)
+                        }
+                        Err(e) => {
+                            format!("Failed to get from database: {}", e)
+                        }
+                    },
+                    Err(e) => e,
+                };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::List {
+            page,
+            owner,
+            prefix,
+            tag,
+        } => {
+            let filter = list_filter(owner, prefix, tag, &message.user.id, &scope);
+            let text = match page_offset(page) {
+                Ok(offset) => {
+                    let result = resource
+                        .store
+                        .list(&filter, LIST_PAGE_SIZE as i64, offset)
+                        .await;
+                    match result {
+                        Ok(entries) => list_text(&entries, page, &scope),
+                        Err(e) => {
+                            format!("Failed to get from database: {}", e)
+                        }
+                    }
+                }
+                Err(e) => e,
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Ranking(period) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let usage = db::UsageFilter {
+                days: period.days(),
+                ..Default::default()
+            };
+            let filter = db::ListFilter {
+                visible_prefixes: scope.visible_prefixes(),
+                ..Default::default()
+            };
+            let result = tokio::try_join!(
+                db::key_ranking(&pool, &usage, &filter, RANKING_SIZE as i64),
+                db::user_ranking(&pool, &usage, RANKING_SIZE as i64)
+            );
+            let text = match result {
+                Ok((keys, users)) => ranking_text(period, &keys, &users, &scope),
+                Err(e) => {
+                    format!("Failed to get from database: {}", e)
+                }
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::MyStats => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let usage = db::UsageFilter {
+                user_id: Some(message.user.id.clone()),
+                ..Default::default()
+            };
+            let filter = db::ListFilter {
+                visible_prefixes: scope.visible_prefixes(),
+                ..Default::default()
+            };
+            let result = tokio::try_join!(
+                db::count_calls(&pool, &usage),
+                db::owner_stats(&pool, &message.user.id),
+                db::key_ranking(&pool, &usage, &filter, RANKING_SIZE as i64)
+            );
+            let text = match result {
+                Ok((calls, owned, keys)) => my_stats_text(calls, &owned, &keys, &scope),
+                Err(e) => {
+                    format!("Failed to get from database: {}", e)
+                }
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Search { words, tag } => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let filter = db::ListFilter {
+                tag,
+                visible_prefixes: scope.visible_prefixes(),
+                ..Default::default()
+            };
+            let result = db::search(&pool, &filter, &words, LIST_PAGE_SIZE as i64).await;
+            let text = match result {
+                Ok(entries) => search_text(&entries, &scope),
+                Err(e) => {
+                    format!("Failed to get from database: {}", e)
+                }
+            };
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Export(options) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let text = backup::export(
+                &pool,
+                &message.user.id,
+                &message.channel_id,
+                &scope,
+                options,
+            )
+            .await;
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Import(options) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let text = backup::import(
+                &pool,
+                resource.store.as_ref(),
+                &message.user,
+                &scope,
+                options,
+            )
+            .await;
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Admin(command) => {
+            let Some(pool) = mariadb(&resource, &message.channel_id).await else {
+                return;
+            };
+            let text = admin::handle(&pool, &message.user, &message.embedded, command).await;
+            let res = send_message(&message.channel_id, &text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+        Command::Join => {
+            let res = join_channel(&message.channel_id).await;
+            if let Err(e) = res {
+                log::error!("Failed to join channel: {:?}", e);
+            }
+        }
+        Command::Leave => {
+            let res = leave_channel(&message.channel_id).await;
+            if let Err(e) = res {
+                log::error!("Failed to leave channel: {:?}", e);
+            }
+        }
+        Command::Help => {
+            let text = "[README](https://github.com/SSlime-s/BOT_SRegexp/blob/main/README.md)";
+            let res = send_message(&message.channel_id, text, true).await;
+            if let Err(e) = res {
+                log::error!("Failed to send message: {:?}", e);
+            }
+        }
+    }
+}
+
+/// 管理者用のコマンドを管理者以外が使った場合や、
+/// BAN されたユーザーが正規表現を変更しようとした場合は、返信する文章を返す
+async fn deny_reason(command: &Command, user_id: &str, resource: &Resource) -> Option<String> {
+    if matches!(command, Command::Admin(_)) {
+        return (!permission::is_admin(user_id)).then(|| "Only admins can do this".to_string());
+    }
+    // BAN は MariaDB を使っている場合だけ記録される
+    let Some(pool) = &resource.pool else {
+        return None;
+    };
+    if !command.modifies_patterns() {
+        return None;
+    }
+    match db::is_banned(pool, user_id).await {
+        Ok(true) => Some("You are banned from saving or editing patterns".to_string()),
+        Ok(false) => None,
+        Err(e) => Some(format!("Failed to get from database: {}", e)),
+    }
+}
+
 pub async fn on_message_created(payload: payload::MessageCreated, resource: Arc<Resource>) {
     log::debug!("Received message created: {:?}", payload);
     let message = payload.message;
@@ -208,21 +1345,202 @@ pub async fn on_direct_message_created(
 #[derive(Clone, Debug)]
 pub enum Command {
     RandRegexp(String),
-    Save { key: String, value: String },
-    Call(String),
-    Remove(String),
+    Explain(String),
+    Probability {
+        regexp: String,
+        text: String,
+    },
+    Stats(String),
+    Match {
+        target: String,
+        text: String,
+    },
+    Save {
+        key: Key,
+        value: String,
+        options: SaveOptions,
+    },
+    Update {
+        key: Key,
+        value: String,
+        options: SaveOptions,
+    },
+    Call(Key),
+    /// 無作為に選んだ正規表現から生成する
+    Lucky {
+        owner: Option<Owner>,
+        tag: Option<String>,
+        /// お気に入りがあれば、その中から選ぶ
+        prefer_favorites: bool,
+    },
+    Fav(Key),
+    Unfav(Key),
+    Favs(usize),
+    Info(Key),
+    Remove(Key),
+    Restore(Key),
+    Transfer {
+        key: Key,
+        user: String,
+    },
+    Maintainer {
+        action: MaintainerAction,
+        key: Key,
+        user: String,
+    },
+    Alias {
+        alias: Key,
+        key: Key,
+    },
+    Unalias(Key),
+    History(Key),
+    Rollback {
+        key: Key,
+        revision: u32,
+    },
+    List {
+        page: usize,
+        owner: Option<Owner>,
+        prefix: Option<String>,
+        tag: Option<String>,
+    },
+    Search {
+        words: Vec<String>,
+        tag: Option<String>,
+    },
+    Ranking(Period),
+    MyStats,
+    Export(ExportOptions),
+    Import(ImportOptions),
+    Admin(AdminCommand),
     Join,
     Leave,
     Help,
 }
 
+impl Command {
+    /// コマンド中の key を、保存するときの形に変換する
+    fn resolve_keys(&mut self, scope: &Scope) -> Result<(), String> {
+        match self {
+            Command::Transfer { key, .. } | Command::Maintainer { key, .. } => {
+                key.resolve(scope)?;
+                if key.is_private() {
+                    return Err(format!(
+                        "Key \"{}\" is private and cannot be shared with others",
+                        key
+                    ));
+                }
+                Ok(())
+            }
+            Command::Alias { alias, key } => {
+                alias.resolve(scope)?;
+                model::key::validate(alias)?;
+                key.resolve(scope)
+            }
+            Command::Save { key, .. } => {
+                key.resolve(scope)?;
+                model::key::validate(key)
+            }
+            Command::Update { key, .. }
+            | Command::Call(key)
+            | Command::Fav(key)
+            | Command::Unfav(key)
+            | Command::Info(key)
+            | Command::Remove(key)
+            | Command::Restore(key)
+            | Command::Unalias(key)
+            | Command::History(key)
+            | Command::Rollback { key, .. } => key.resolve(scope),
+            Command::Admin(
+                AdminCommand::Remove(key) | AdminCommand::Lock(key) | AdminCommand::Unlock(key),
+            ) => {
+                // 管理者は他のユーザーやチャンネルの key も扱うので、保存するときの形ならそのまま受け付ける
+                if model::key::is_resolved(key) {
+                    Ok(())
+                } else {
+                    key.resolve(scope)
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 保存された正規表現を変更するコマンドか
+    fn modifies_patterns(&self) -> bool {
+        matches!(
+            self,
+            Command::Save { .. }
+                | Command::Update { .. }
+                | Command::Remove(_)
+                | Command::Restore(_)
+                | Command::Rollback { .. }
+                | Command::Transfer { .. }
+                | Command::Maintainer { .. }
+                | Command::Alias { .. }
+                | Command::Unalias(_)
+                | Command::Import(_)
+        )
+    }
+}
+
+/// `/save` と `/update` で、key の前につけるオプション
+#[derive(Clone, Debug, Default)]
+pub struct SaveOptions {
+    /// 保存する前の確認をせずに保存する
+    pub force: bool,
+    pub metadata: db::Metadata,
+}
+
+/// `/ranking` で数える期間
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+    All,
+}
+
+impl Period {
+    fn days(self) -> Option<u32> {
+        match self {
+            Period::Day => Some(1),
+            Period::Week => Some(7),
+            Period::Month => Some(30),
+            Period::All => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Period::Day => "直近 24 時間",
+            Period::Week => "直近 7 日間",
+            Period::Month => "直近 30 日間",
+            Period::All => "これまで",
+        }
+    }
+}
+
+/// `/maintainer` で行う操作
+#[derive(Clone, Copy, Debug)]
+pub enum MaintainerAction {
+    Add,
+    Remove,
+}
+
+/// `/list` や `/lucky` で絞り込む作成者
+#[derive(Clone, Debug)]
+pub enum Owner {
+    Me,
+    Name(String),
+}
+
 /// エラーの prefix に `Optional: ` がある場合は、メンション時にしかエラーを表示しない
 pub fn parse_command(input: &str) -> Result<Command> {
     let content = input.trim();
     let splitted = content.split_whitespace().collect::<Vec<_>>();
 
     anyhow::ensure!(
-        splitted.first().map_or(false, |x| x.starts_with('/')),
+        splitted.first().is_some_and(|x| x.starts_with('/')),
         "Optional: / で始まるコマンドが必須です"
     );
 
@@ -231,31 +1549,893 @@ pub fn parse_command(input: &str) -> Result<Command> {
             let rest = content.trim_start_matches(&format!("/{command}")).trim();
             Ok(Command::RandRegexp(rest.to_string()))
         }
+        command @ ("explain" | "describe") => {
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            Ok(Command::Explain(rest.to_string()))
+        }
+        command @ ("prob" | "probability") => {
+            anyhow::ensure!(splitted.len() >= 2, "正規表現が必須です");
+
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            let regexp = splitted[1].to_string();
+            let text = rest.trim_start_matches(&regexp).trim().to_string();
+            Ok(Command::Probability { regexp, text })
+        }
+        command @ ("stats" | "statistics") => {
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            Ok(Command::Stats(rest.to_string()))
+        }
+        command @ ("match" | "test") => {
+            anyhow::ensure!(splitted.len() >= 2, "key または正規表現が必須です");
+
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            let target = splitted[1].to_string();
+            let text = rest.trim_start_matches(&target).trim().to_string();
+            Ok(Command::Match { target, text })
+        }
         command @ ("save" | "memory") => {
-            anyhow::ensure!(splitted.len() >= 2, "key が必須です");
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            let (options, rest) = parse_save_options(rest)?;
+            let (key, value) = split_key(rest);
+            anyhow::ensure!(!key.is_empty(), "key が必須です");
 
+            let (key, value) = (Key::new(key), value.to_string());
+            Ok(Command::Save {
+                key,
+                value,
+                options,
+            })
+        }
+        command @ ("update" | "overwrite") => {
             let rest = content.trim_start_matches(&format!("/{command}")).trim();
-            let key = splitted[1].to_string();
-            let value = rest.trim_start_matches(&key).trim().to_string();
-            Ok(Command::Save { key, value })
+            let (options, rest) = parse_save_options(rest)?;
+            let (key, value) = split_key(rest);
+            anyhow::ensure!(!key.is_empty(), "key が必須です");
+            anyhow::ensure!(!value.is_empty(), "正規表現が必須です");
+
+            let (key, value) = (Key::new(key), value.to_string());
+            Ok(Command::Update {
+                key,
+                value,
+                options,
+            })
         }
         command @ ("call" | "load") => {
+            if splitted.len() == 1 {
+                return Ok(Command::Lucky {
+                    owner: None,
+                    tag: None,
+                    prefer_favorites: true,
+                });
+            }
+            anyhow::ensure!(splitted.len() <= 2, "key に空白を含めることはできません");
+
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            Ok(Command::Call(Key::new(rest)))
+        }
+        "lucky" => {
+            let mut owner = None;
+            let mut tag = None;
+            let mut args = splitted[1..].iter();
+            while let Some(&arg) = args.next() {
+                match arg {
+                    "--mine" => owner = Some(Owner::Me),
+                    "--user" => {
+                        let name = args.next().context("--user には作成者の名前が必須です")?;
+                        owner = Some(Owner::Name(name.trim_start_matches('@').to_string()));
+                    }
+                    "--tag" => {
+                        let value = args.next().context("--tag にはタグが必須です")?;
+                        tag = Some(value.to_string());
+                    }
+                    _ => anyhow::bail!("不明な引数です: {}", arg),
+                }
+            }
+            Ok(Command::Lucky {
+                owner,
+                tag,
+                prefer_favorites: false,
+            })
+        }
+        command @ ("fav" | "favorite") => {
+            anyhow::ensure!(splitted.len() >= 2, "key が必須です");
+            anyhow::ensure!(splitted.len() <= 2, "key に空白を含めることはできません");
+
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            Ok(Command::Fav(Key::new(rest)))
+        }
+        command @ ("unfav" | "unfavorite") => {
+            anyhow::ensure!(splitted.len() >= 2, "key が必須です");
+            anyhow::ensure!(splitted.len() <= 2, "key に空白を含めることはできません");
+
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            Ok(Command::Unfav(Key::new(rest)))
+        }
+        "favs" | "favorites" => {
+            anyhow::ensure!(
+                splitted.len() <= 2,
+                "不明な引数です: {}",
+                splitted[2..].join(" ")
+            );
+
+            let page = match splitted.get(1) {
+                Some(arg) => arg
+                    .parse()
+                    .ok()
+                    .filter(|&page| page >= 1)
+                    .with_context(|| format!("不明な引数です: {}", arg))?,
+                None => 1,
+            };
+            Ok(Command::Favs(page))
+        }
+        command @ ("info" | "show") => {
             anyhow::ensure!(splitted.len() >= 2, "key が必須です");
             anyhow::ensure!(splitted.len() <= 2, "key に空白を含めることはできません");
 
             let rest = content.trim_start_matches(&format!("/{command}")).trim();
-            Ok(Command::Call(rest.to_string()))
+            Ok(Command::Info(Key::new(rest)))
         }
         command @ ("remove" | "delete" | "forget") => {
             anyhow::ensure!(splitted.len() >= 2, "key が必須です");
             anyhow::ensure!(splitted.len() <= 2, "key に空白を含めることはできません");
 
             let rest = content.trim_start_matches(&format!("/{command}")).trim();
-            Ok(Command::Remove(rest.to_string()))
+            Ok(Command::Remove(Key::new(rest)))
+        }
+        command @ ("restore" | "undelete") => {
+            anyhow::ensure!(splitted.len() >= 2, "key が必須です");
+            anyhow::ensure!(splitted.len() <= 2, "key に空白を含めることはできません");
+
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            Ok(Command::Restore(Key::new(rest)))
+        }
+        "transfer" | "give" => {
+            anyhow::ensure!(splitted.len() >= 2, "key が必須です");
+            anyhow::ensure!(splitted.len() >= 3, "渡す相手のユーザーが必須です");
+            anyhow::ensure!(splitted.len() <= 3, "key に空白を含めることはできません");
+
+            let key = Key::new(splitted[1]);
+            let user = splitted[2].to_string();
+            Ok(Command::Transfer { key, user })
+        }
+        "maintainer" | "maintainers" => {
+            anyhow::ensure!(splitted.len() >= 2, "add か remove が必須です");
+            let action = match splitted[1] {
+                "add" => MaintainerAction::Add,
+                "remove" | "rm" => MaintainerAction::Remove,
+                action => anyhow::bail!("不明な操作です: {}", action),
+            };
+            anyhow::ensure!(splitted.len() >= 3, "key が必須です");
+            anyhow::ensure!(splitted.len() >= 4, "共同管理者にするユーザーが必須です");
+            anyhow::ensure!(splitted.len() <= 4, "key に空白を含めることはできません");
+
+            let key = Key::new(splitted[2]);
+            let user = splitted[3].to_string();
+            Ok(Command::Maintainer { action, key, user })
+        }
+        "alias" => {
+            anyhow::ensure!(splitted.len() >= 2, "新しい名前が必須です");
+            anyhow::ensure!(splitted.len() >= 3, "別名をつける key が必須です");
+            anyhow::ensure!(splitted.len() <= 3, "key に空白を含めることはできません");
+
+            let alias = Key::new(splitted[1]);
+            let key = Key::new(splitted[2]);
+            Ok(Command::Alias { alias, key })
         }
+        command @ ("unalias" | "remove-alias") => {
+            anyhow::ensure!(splitted.len() >= 2, "別名が必須です");
+            anyhow::ensure!(splitted.len() <= 2, "key に空白を含めることはできません");
+
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            Ok(Command::Unalias(Key::new(rest)))
+        }
+        command @ ("history" | "log") => {
+            anyhow::ensure!(splitted.len() >= 2, "key が必須です");
+            anyhow::ensure!(splitted.len() <= 2, "key に空白を含めることはできません");
+
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            Ok(Command::History(Key::new(rest)))
+        }
+        "rollback" | "revert" => {
+            anyhow::ensure!(splitted.len() >= 2, "key が必須です");
+            anyhow::ensure!(splitted.len() >= 3, "戻したい履歴の番号が必須です");
+            anyhow::ensure!(splitted.len() <= 3, "key に空白を含めることはできません");
+
+            let key = Key::new(splitted[1]);
+            let revision = splitted[2]
+                .trim_start_matches('#')
+                .parse()
+                .ok()
+                .with_context(|| format!("履歴の番号が不正です: {}", splitted[2]))?;
+            Ok(Command::Rollback { key, revision })
+        }
+        "list" | "ls" => {
+            let mut page = 1;
+            let mut owner = None;
+            let mut prefix = None;
+            let mut tag = None;
+            let mut args = splitted[1..].iter();
+            while let Some(&arg) = args.next() {
+                match arg {
+                    "--mine" => owner = Some(Owner::Me),
+                    "--user" => {
+                        let name = args.next().context("--user には作成者の名前が必須です")?;
+                        owner = Some(Owner::Name(name.trim_start_matches('@').to_string()));
+                    }
+                    "--prefix" => {
+                        let value = args.next().context("--prefix には key の先頭が必須です")?;
+                        prefix = Some(value.to_string());
+                    }
+                    "--tag" => {
+                        let value = args.next().context("--tag にはタグが必須です")?;
+                        tag = Some(value.to_string());
+                    }
+                    _ => {
+                        page = arg
+                            .parse()
+                            .ok()
+                            .filter(|&page| page >= 1)
+                            .with_context(|| format!("不明な引数です: {}", arg))?;
+                    }
+                }
+            }
+            Ok(Command::List {
+                page,
+                owner,
+                prefix,
+                tag,
+            })
+        }
+        "ranking" | "rank" => {
+            anyhow::ensure!(splitted.len() <= 2, "期間は 1 つだけ指定してください");
+
+            let period = match splitted.get(1).copied() {
+                Some("day" | "daily") => Period::Day,
+                Some("week" | "weekly") => Period::Week,
+                Some("month" | "monthly") => Period::Month,
+                Some("all") | None => Period::All,
+                Some(period) => anyhow::bail!("不明な期間です: {}", period),
+            };
+            Ok(Command::Ranking(period))
+        }
+        "mystats" | "me" => Ok(Command::MyStats),
+        "search" | "find" => {
+            let mut words = vec![];
+            let mut tag = None;
+            let mut args = splitted[1..].iter();
+            while let Some(&arg) = args.next() {
+                match arg {
+                    "--tag" => {
+                        let value = args.next().context("--tag にはタグが必須です")?;
+                        tag = Some(value.to_string());
+                    }
+                    _ => words.push(arg.to_string()),
+                }
+            }
+            anyhow::ensure!(!words.is_empty(), "探す言葉が必須です");
+            Ok(Command::Search { words, tag })
+        }
+        "export" => Ok(Command::Export(parse_export_options(&splitted[1..])?)),
+        command @ "import" => {
+            let rest = content.trim_start_matches(&format!("/{command}")).trim();
+            Ok(Command::Import(parse_import_options(rest)?))
+        }
+        "admin" => Ok(Command::Admin(parse_admin_command(&splitted[1..])?)),
         "join" => Ok(Command::Join),
         "leave" | "bye" => Ok(Command::Leave),
         "help" | "usage" | "readme" => Ok(Command::Help),
         unknown => anyhow::bail!("Optional: unknown command /{}", unknown),
     }
 }
+
+/// 別名と、別名をつける key の範囲が違う場合に返信する文章
+/// 範囲の違う別名を許すと、自分だけの key や他のチャンネルの key を誰でも呼び出せてしまう
+fn alias_scope_error(alias: &Key, found: &str, scope: &Scope) -> String {
+    format!(
+        "別名は、別名をつける key と同じ範囲にしか作れません ({} => {})",
+        alias,
+        scope.display(found)
+    )
+}
+
+/// 履歴や別名などを扱うコマンドのために、MariaDB への接続を返す
+/// MariaDB を使っていない場合は、使えないことを返信して `None` を返す
+async fn mariadb(resource: &Resource, channel_id: &str) -> Option<MySqlPool> {
+    if let Some(pool) = &resource.pool {
+        return Some(pool.clone());
+    }
+    let text = "このコマンドは、MariaDB を使っている場合だけ使えます";
+    let res = send_message(channel_id, text, true).await;
+    if let Err(e) = res {
+        log::error!("Failed to send message: {:?}", e);
+    }
+    None
+}
+
+/// 正規表現を保存し、返信する文章を返す
+async fn save_pattern(
+    resource: &Resource,
+    key: &Key,
+    value: &str,
+    metadata: &db::Metadata,
+    user_id: &str,
+    user_name: &str,
+    scope: &Scope,
+) -> String {
+    if let Some(pool) = &resource.pool {
+        match db::get_alias(pool, key).await {
+            Ok(Some(alias)) => {
+                return format!(
+                    "Key \"{}\" is already used as an alias of {}",
+                    key,
+                    scope.display(&alias.key)
+                )
+            }
+            Ok(None) => {}
+            Err(e) => return format!("Failed to get from database: {}", e),
+        }
+    }
+
+    let result = resource
+        .store
+        .save(key, value, metadata, user_id, user_name)
+        .await;
+    match result {
+        Ok(true) => format!("Saved: {} => {}", key, value),
+        Ok(false) => {
+            let deleted_at = match &resource.pool {
+                Some(pool) => db::deleted_at(pool, key).await.ok().flatten(),
+                None => None,
+            };
+            match deleted_at {
+                Some(deleted_at) => removed_key_text(key, deleted_at),
+                None => format!("Key \"{}\" is already exists", key),
+            }
+        }
+        Err(e) => format!("Failed to save: {}", e),
+    }
+}
+
+/// `/call` で呼び出された回数を数え、呼び出しを記録する
+async fn record_call(pool: &MySqlPool, key: &str, user: &User, channel_id: &str) {
+    let result = tokio::try_join!(
+        db::increment_call_count(pool, key),
+        db::record_call(pool, key, &user.id, &user.name, channel_id)
+    );
+    if let Err(e) = result {
+        log::error!("Failed to record call: {:?}", e);
+    }
+}
+
+/// 無作為に選んだ正規表現から生成し、選んだ key と生成した文字列を返す
+/// 生成できなかった場合は、`LUCKY_ATTEMPTS` 回まで選び直す
+async fn generate_lucky(
+    pool: &MySqlPool,
+    filter: &db::ListFilter,
+) -> sqlx::Result<Option<(String, String)>> {
+    for _ in 0..LUCKY_ATTEMPTS {
+        let Some(entry) = db::random_entry(pool, filter).await? else {
+            return Ok(None);
+        };
+        match generate_text(&entry.regexp).await {
+            Ok(text) => return Ok(Some((entry.key, text))),
+            Err(e) => log::debug!("Skipped \"{}\" for /lucky: {}", entry.key, e),
+        }
+    }
+    Ok(None)
+}
+
+/// `/list` や `/lucky` で指定された条件を、データベースで絞り込む条件にする
+fn list_filter(
+    owner: Option<Owner>,
+    prefix: Option<String>,
+    tag: Option<String>,
+    user_id: &str,
+    scope: &Scope,
+) -> db::ListFilter {
+    db::ListFilter {
+        user_id: matches!(owner, Some(Owner::Me)).then(|| user_id.to_string()),
+        user_name: match owner {
+            Some(Owner::Name(name)) => Some(name),
+            _ => None,
+        },
+        prefix: prefix.map(|p| scope.resolve(&p).unwrap_or(p)),
+        tag,
+        visible_prefixes: scope.visible_prefixes(),
+        ..Default::default()
+    }
+}
+
+/// メッセージ中のメンション `@name` から、メンションされたユーザーを探す
+fn mentioned_user<'a>(embedded: &'a [EmbeddedInfo], raw: &str) -> Option<&'a EmbeddedInfo> {
+    embedded.iter().find(|e| e.type_ == "user" && e.raw == raw)
+}
+
+/// 先頭の `--force`, `--desc`, `--tag`, `--no-tag` を取り除き、オプションと残りの部分を返す
+fn parse_save_options(mut rest: &str) -> Result<(SaveOptions, &str)> {
+    let mut options = SaveOptions::default();
+    loop {
+        if let Some(r) = strip_flag(rest, "--force") {
+            options.force = true;
+            rest = r;
+        } else if let Some(r) = strip_flag(rest, "--desc") {
+            let (description, r) = if r.starts_with('"') {
+                split_quoted(r)?
+            } else {
+                let (word, r) = split_key(r);
+                (word.to_string(), r)
+            };
+            anyhow::ensure!(!description.is_empty(), "--desc には説明が必須です");
+            anyhow::ensure!(
+                description.chars().count() <= DESCRIPTION_LENGTH_LIMIT,
+                "説明は {} 文字以内にしてください",
+                DESCRIPTION_LENGTH_LIMIT
+            );
+            options.metadata.description = Some(description);
+            rest = r;
+        } else if let Some(r) = strip_flag(rest, "--tag") {
+            let (tags, r) = split_key(r);
+            anyhow::ensure!(!tags.is_empty(), "--tag にはタグが必須です");
+            for tag in tags.split(',') {
+                validate_tag(tag).map_err(anyhow::Error::msg)?;
+                options
+                    .metadata
+                    .tags
+                    .get_or_insert_with(Vec::new)
+                    .push(tag.to_string());
+            }
+            rest = r;
+        } else if let Some(r) = strip_flag(rest, "--no-tag") {
+            options.metadata.tags.get_or_insert_with(Vec::new).clear();
+            rest = r;
+        } else {
+            return Ok((options, rest));
+        }
+    }
+}
+
+/// 先頭の `flag` を取り除き、残りの部分を返す
+fn strip_flag<'a>(rest: &'a str, flag: &str) -> Option<&'a str> {
+    match rest.strip_prefix(flag) {
+        Some(r) if r.is_empty() || r.starts_with(char::is_whitespace) => Some(r.trim_start()),
+        _ => None,
+    }
+}
+
+/// `"` で囲まれた部分と、残りの部分に分ける
+/// 囲まれた部分の中では、`\"` で `"` を表す
+fn split_quoted(rest: &str) -> Result<(String, &str)> {
+    let mut quoted = String::new();
+    let mut chars = rest.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((quoted, rest[i + 1..].trim_start())),
+            '\\' => match chars.next() {
+                Some((_, c @ ('"' | '\\'))) => quoted.push(c),
+                Some((_, c)) => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                None => break,
+            },
+            c => quoted.push(c),
+        }
+    }
+    anyhow::bail!("説明の \" が閉じられていません")
+}
+
+/// 先頭の key と、残りの部分に分ける
+fn split_key(rest: &str) -> (&str, &str) {
+    match rest.split_once(char::is_whitespace) {
+        Some((key, value)) => (key, value.trim()),
+        None => (rest, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::store::MemoryStore;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_list_command() {
+        assert!(matches!(
+            parse_command("/list"),
+            Ok(Command::List {
+                page: 1,
+                owner: None,
+                prefix: None,
+                tag: None,
+            })
+        ));
+        assert!(matches!(
+            parse_command("/list 3 --user @SSlime --prefix tr --tag omikuji"),
+            Ok(Command::List {
+                page: 3,
+                owner: Some(Owner::Name(name)),
+                prefix: Some(prefix),
+                tag: Some(tag),
+            }) if name == "SSlime" && prefix == "tr" && tag == "omikuji"
+        ));
+        assert!(matches!(
+            parse_command("/ls --mine"),
+            Ok(Command::List {
+                owner: Some(Owner::Me),
+                ..
+            })
+        ));
+        assert!(parse_command("/list 0").is_err());
+        assert!(parse_command("/list --user").is_err());
+        assert!(parse_command("/list --tag").is_err());
+    }
+
+    #[test]
+    fn test_page_offset() {
+        assert_eq!(page_offset(1), Ok(0));
+        assert_eq!(page_offset(3), Ok(2 * LIST_PAGE_SIZE as i64));
+        assert!(page_offset(0).is_err());
+        assert_eq!(
+            page_offset(usize::MAX),
+            Err(format!("{} ページ目はありません", usize::MAX))
+        );
+        assert!(page_offset(usize::MAX / LIST_PAGE_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_parse_save_command() {
+        assert!(matches!(
+            parse_command("/save traP [a-z]{3} [A-Z]"),
+            Ok(Command::Save { key, value, options })
+                if key == "traP" && value == "[a-z]{3} [A-Z]" && !options.force
+        ));
+        assert!(matches!(
+            parse_command("/save --force traP [a-z"),
+            Ok(Command::Save { key, value, options })
+                if key == "traP" && value == "[a-z" && options.force
+        ));
+        assert!(matches!(
+            parse_command("/save --forced a"),
+            Ok(Command::Save { key, options, .. }) if key == "--forced" && !options.force
+        ));
+        assert!(parse_command("/save --force").is_err());
+        assert!(matches!(
+            parse_command("/update --force traP [a-z"),
+            Ok(Command::Update { key, value, options })
+                if key == "traP" && value == "[a-z" && options.force
+        ));
+    }
+
+    #[test]
+    fn test_check_pattern() {
+        let key = Key::new("traP");
+        assert!(check_pattern("update", &key, "[a-z]", false).is_ok());
+        assert!(check_pattern("update", &key, "[a-z", true).is_ok());
+
+        for command in ["save", "update"] {
+            let text = check_pattern(command, &key, "[a-z", false).unwrap_err();
+            assert!(text.starts_with("Failed to parse"));
+            assert!(text.ends_with(&format!(
+                "`/{} --force traP {{正規表現}}` としてください",
+                command
+            )));
+        }
+    }
+
+    #[test]
+    fn test_parse_save_metadata() {
+        let Ok(Command::Save {
+            key,
+            value,
+            options,
+        }) = parse_command(
+            r#"/save --desc "今日の \"運勢\"" --tag omikuji,fortune omikuji (大|小)吉"#,
+        )
+        else {
+            panic!("failed to parse");
+        };
+        assert_eq!(key, "omikuji");
+        assert_eq!(value, "(大|小)吉");
+        assert_eq!(
+            options.metadata,
+            db::Metadata {
+                description: Some("今日の \"運勢\"".to_string()),
+                tags: Some(vec!["omikuji".to_string(), "fortune".to_string()]),
+            }
+        );
+        assert!(matches!(
+            parse_command("/update --tag name --desc 名前 --force traP [a-z"),
+            Ok(Command::Update { options, .. })
+                if options.force && options.metadata.description.as_deref() == Some("名前")
+        ));
+        assert!(parse_command(r#"/save --desc "閉じない traP a"#).is_err());
+        assert!(parse_command("/save --tag omikuji,,name traP a").is_err());
+        assert!(parse_command("/save --tag").is_err());
+
+        let Ok(Command::Update { options, .. }) = parse_command("/update traP a") else {
+            panic!("failed to parse");
+        };
+        assert_eq!(options.metadata.tags, None);
+        let Ok(Command::Update { options, .. }) = parse_command("/update --no-tag traP a") else {
+            panic!("failed to parse");
+        };
+        assert_eq!(options.metadata.tags, Some(vec![]));
+    }
+
+    #[test]
+    fn test_parse_search_command() {
+        assert!(matches!(
+            parse_command("/search --tag omikuji 吉 凶"),
+            Ok(Command::Search { words, tag: Some(tag) })
+                if words == ["吉", "凶"] && tag == "omikuji"
+        ));
+        assert!(parse_command("/search --tag omikuji").is_err());
+        assert!(matches!(
+            parse_command("/find おみくじ"),
+            Ok(Command::Search { words, tag: None }) if words == ["おみくじ"]
+        ));
+        assert!(parse_command("/find").is_err());
+    }
+
+    #[test]
+    fn test_search_text() {
+        let scope = Scope::new("user", "channel", &[]);
+        assert_eq!(search_text(&[], &scope), "一致する正規表現はありません");
+
+        let entries = [db::Entry {
+            key: "~user/omikuji".to_string(),
+            regexp: "大吉|凶".to_string(),
+            user_name: "SSlime".to_string(),
+        }];
+        let text = search_text(&entries, &scope);
+        assert_eq!(
+            text.lines().last(),
+            Some("| ~me/omikuji | SSlime | ` 大吉\\|凶 ` |")
+        );
+    }
+
+    #[test]
+    fn test_parse_lucky_command() {
+        assert!(matches!(
+            parse_command("/call"),
+            Ok(Command::Lucky {
+                owner: None,
+                tag: None,
+                prefer_favorites: true,
+            })
+        ));
+        assert!(matches!(
+            parse_command("/lucky --user @SSlime --tag omikuji"),
+            Ok(Command::Lucky {
+                owner: Some(Owner::Name(name)),
+                tag: Some(tag),
+                prefer_favorites: false,
+            }) if name == "SSlime" && tag == "omikuji"
+        ));
+        assert!(parse_command("/lucky traP").is_err());
+        assert!(parse_command("/lucky --tag").is_err());
+    }
+
+    #[test]
+    fn test_parse_favorite_command() {
+        assert!(matches!(parse_command("/fav traP"), Ok(Command::Fav(key)) if key == "traP"));
+        assert!(
+            matches!(parse_command("/unfav ~me/traP"), Ok(Command::Unfav(key)) if key == "~me/traP")
+        );
+        assert!(matches!(parse_command("/favs"), Ok(Command::Favs(1))));
+        assert!(matches!(parse_command("/favs 2"), Ok(Command::Favs(2))));
+        assert!(parse_command("/fav").is_err());
+        assert!(parse_command("/favs 0").is_err());
+        assert!(parse_command("/favs 1 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_ranking_command() {
+        assert!(matches!(
+            parse_command("/ranking"),
+            Ok(Command::Ranking(Period::All))
+        ));
+        assert!(matches!(
+            parse_command("/rank week"),
+            Ok(Command::Ranking(Period::Week))
+        ));
+        assert!(parse_command("/ranking year").is_err());
+        assert!(parse_command("/ranking day week").is_err());
+    }
+
+    #[test]
+    fn test_parse_maintainer_command() {
+        assert!(matches!(
+            parse_command("/maintainer add traP @SSlime"),
+            Ok(Command::Maintainer { action: MaintainerAction::Add, key, user })
+                if key == "traP" && user == "@SSlime"
+        ));
+        assert!(matches!(
+            parse_command("/maintainer rm traP @SSlime"),
+            Ok(Command::Maintainer {
+                action: MaintainerAction::Remove,
+                ..
+            })
+        ));
+        assert!(parse_command("/maintainer invite traP @SSlime").is_err());
+        assert!(parse_command("/maintainer add traP").is_err());
+    }
+
+    #[test]
+    fn test_parse_rollback_command() {
+        assert!(matches!(
+            parse_command("/rollback traP 3"),
+            Ok(Command::Rollback { key, revision: 3 }) if key == "traP"
+        ));
+        assert!(parse_command("/rollback traP").is_err());
+        assert!(parse_command("/rollback traP latest").is_err());
+    }
+
+    #[test]
+    fn test_reserved_names_are_commands() {
+        for name in model::key::RESERVED_NAMES {
+            let unknown = parse_command(&format!("/{}", name))
+                .is_err_and(|e| e.to_string().contains("unknown command"));
+            assert!(!unknown, "/{} is not a command", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_blocking_timeout() {
+        let text = run_blocking(Duration::from_millis(10), || {
+            std::thread::sleep(Duration::from_millis(200));
+            Ok("done".to_string())
+        })
+        .await;
+        assert_eq!(text, Err("Timed out: took more than 10 ms".to_string()));
+
+        let text = run_blocking(Duration::from_secs(1), || Ok("done".to_string())).await;
+        assert_eq!(text, Ok("done".to_string()));
+    }
+
+    #[test]
+    fn test_nested_empty_repeat() {
+        let regexp = "(((a{0}){10000}){10000}){10000}";
+        assert!(probability_text(regexp, "").is_ok_and(|text| text.ends_with("100.0000%")));
+        assert!(stats_text(regexp).is_ok());
+        assert!(match_text(regexp, "").is_ok_and(|text| text.ends_with("生成されえます")));
+    }
+
+    #[test]
+    fn test_validate_pattern() {
+        for regexp in ["(大|小)吉|凶", "a\\d{3}", "[\\w-]+"] {
+            assert!(validate_pattern(regexp).is_ok(), "{}", regexp);
+        }
+        // 生成してみると、選ばれた枝によって成功したり失敗したりするもの
+        for _ in 0..20 {
+            assert!(validate_pattern("a|\\q").is_err());
+        }
+        assert!(validate_pattern("(a|\\q){0}b").is_err());
+        assert!(validate_pattern("(a").unwrap_err().contains('^'));
+        assert!(validate_pattern("a{3001}").is_err());
+        assert!(validate_pattern("[z-a]").is_err());
+    }
+
+    #[test]
+    fn test_alias_scope() {
+        let scope = Scope::new("user", "channel", &[]);
+        let resolved = |input: &str| {
+            let mut key = Key::new(input);
+            key.resolve(&scope).unwrap();
+            key
+        };
+
+        let allowed = [
+            ("o", "omikuji"),
+            ("~me/o", "~me/omikuji"),
+            ("#/o", "#/omikuji"),
+        ];
+        for (alias, key) in allowed {
+            assert!(model::key::same_scope(&resolved(alias), &resolved(key)));
+        }
+        let rejected = [
+            ("o", "~me/omikuji"),
+            ("o", "#/omikuji"),
+            ("#/o", "~me/omikuji"),
+        ];
+        for (alias, key) in rejected {
+            let (alias, key) = (resolved(alias), resolved(key));
+            assert!(!model::key::same_scope(&alias, &key));
+            let text = alias_scope_error(&alias, &key, &scope);
+            assert!(text.ends_with(&format!("({} => {})", alias, scope.display(&key))));
+        }
+    }
+
+    #[test]
+    fn test_save_key_validation() {
+        let scope = Scope::new("user", "channel", &[]);
+        let mut command = parse_command("/save ~me/omikuji (大|小)吉|凶").unwrap();
+        assert!(command.resolve_keys(&scope).is_ok());
+
+        for input in ["/save list a", "/save ~me/a`b a", "/alias help omikuji"] {
+            let mut command = parse_command(input).unwrap();
+            assert!(command.resolve_keys(&scope).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_admin_key_resolution() {
+        let scope = Scope::new("user", "channel", &[]);
+        let other = "~01234567-89ab-cdef-0123-456789abcdef/x";
+        for action in ["remove", "lock", "unlock"] {
+            let mut command = parse_command(&format!("/admin {} {}", action, other)).unwrap();
+            assert!(command.resolve_keys(&scope).is_ok(), "{}", action);
+            let Command::Admin(
+                AdminCommand::Remove(key) | AdminCommand::Lock(key) | AdminCommand::Unlock(key),
+            ) = command
+            else {
+                panic!("{}", action);
+            };
+            assert_eq!(&*key, other);
+        }
+
+        let mut command = parse_command("/admin remove ~me/x").unwrap();
+        assert!(command.resolve_keys(&scope).is_ok());
+        let mut command = parse_command("/save ~01234567-89ab-cdef-0123-456789abcdef/x a").unwrap();
+        assert!(command.resolve_keys(&scope).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_save_with_memory_store() {
+        let resource = Resource {
+            store: Box::<MemoryStore>::default(),
+            pool: None,
+        };
+        let scope = Scope::new("user", "channel", &[]);
+        for input in ["omikuji", "~me/omikuji"] {
+            let mut key = Key::new(input);
+            key.resolve(&scope).unwrap();
+            let text = save_pattern(
+                &resource,
+                &key,
+                input,
+                &db::Metadata::default(),
+                "user",
+                "SSlime",
+                &scope,
+            )
+            .await;
+            assert_eq!(text, format!("Saved: {} => {}", input, input));
+        }
+
+        let key = Key::new("omikuji");
+        let text = save_pattern(
+            &resource,
+            &key,
+            "吉",
+            &db::Metadata::default(),
+            "other",
+            "other",
+            &scope,
+        )
+        .await;
+        assert_eq!(text, "Key \"omikuji\" is already exists");
+
+        // 自分の key が、全体の key より優先される
+        let candidates = scope.lookup("omikuji").unwrap();
+        let found = resource.store.find(&candidates).await.unwrap();
+        assert_eq!(
+            found.map(|(_, value)| value).as_deref(),
+            Some("~me/omikuji")
+        );
+
+        let store = resource.store.as_ref();
+        assert!(
+            permission::check(store, "omikuji", "other", Role::Maintainer)
+                .await
+                .is_err()
+        );
+        assert!(permission::check(store, "omikuji", "user", Role::Owner)
+            .await
+            .is_ok());
+        assert_eq!(
+            permission::check(store, "missing", "user", Role::Owner).await,
+            Err("Key \"missing\" is not found".to_string())
+        );
+    }
+}
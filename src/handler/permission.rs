@@ -0,0 +1,33 @@
+use crate::{config::ADMIN_USER_IDS, model::store::PatternStore};
+
+pub use crate::model::store::Role;
+
+pub fn is_admin(user_id: &str) -> bool {
+    ADMIN_USER_IDS.iter().any(|id| id == user_id)
+}
+
+/// `user_id` が key に対して `required` 以上の権限を持つかを確かめる
+/// 管理者にロックされている key は、誰も操作できない
+/// 持たない場合は、返信する文章を返す
+///
+/// 権限とロックは保存先から取得するので、MariaDB 以外を使っている場合は保存した人だけが操作できる
+pub async fn check(
+    store: &dyn PatternStore,
+    key: &str,
+    user_id: &str,
+    required: Role,
+) -> Result<(), String> {
+    match store.role(key, user_id).await {
+        Ok(Some(role)) if role >= required => match store.is_locked(key).await {
+            Ok(false) => Ok(()),
+            Ok(true) => Err(format!("Key \"{}\" is locked by an admin", key)),
+            Err(e) => Err(format!("Failed to get from database: {}", e)),
+        },
+        Ok(Some(_)) => Err(match required {
+            Role::Owner => format!("Only the owner of \"{}\" can do this", key),
+            _ => format!("Only the owner and maintainers of \"{}\" can do this", key),
+        }),
+        Ok(None) => Err(format!("Key \"{}\" is not found", key)),
+        Err(e) => Err(format!("Failed to get from database: {}", e)),
+    }
+}
@@ -0,0 +1,230 @@
+use std::{fmt, ops::Deref};
+
+use traq_ws_bot::events::common::EmbeddedInfo;
+
+use crate::model::key::is_uuid;
+
+/// key の範囲を表す接頭辞
+///
+/// 保存するときは、チャンネルの key は `#{チャンネルの UUID}/name`、
+/// ユーザーの key は `~{ユーザーの UUID}/name` として、全体の key と同じ表に保存する
+const CHANNEL_PREFIX: char = '#';
+const USER_PREFIX: char = '~';
+
+/// key を解決するときに使う、メッセージを送ったユーザーとチャンネル
+#[derive(Debug, Clone)]
+pub struct Scope {
+    user_id: String,
+    channel_id: String,
+    /// メッセージ中でメンションされたチャンネルの `#name` と UUID
+    channels: Vec<(String, String)>,
+}
+
+impl Scope {
+    pub fn new(user_id: &str, channel_id: &str, embedded: &[EmbeddedInfo]) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            channel_id: channel_id.to_string(),
+            channels: embedded
+                .iter()
+                .filter(|e| e.type_ == "channel")
+                .map(|e| (e.raw.clone(), e.id.clone()))
+                .collect(),
+        }
+    }
+
+    fn user_prefix(&self) -> String {
+        format!("{}{}/", USER_PREFIX, self.user_id)
+    }
+
+    pub fn channel_prefix(&self) -> String {
+        format!("{}{}/", CHANNEL_PREFIX, self.channel_id)
+    }
+
+    /// `~me/name`, `#/name`, `#channel/name` を保存するときの key に変換する
+    /// 範囲のない key はそのまま返す
+    pub fn resolve(&self, key: &str) -> Result<String, String> {
+        if let Some(rest) = key.strip_prefix(USER_PREFIX) {
+            let (user, name) = rest
+                .split_once('/')
+                .ok_or_else(|| format!("Key \"{}\" must be like ~me/name", key))?;
+            return match user {
+                "" | "me" => Ok(format!("{}{}", self.user_prefix(), name)),
+                user if user == self.user_id => Ok(key.to_string()),
+                _ => Err(format!("Key \"{}\" belongs to another user", key)),
+            };
+        }
+        if key.starts_with(CHANNEL_PREFIX) {
+            if let Some(name) = key.strip_prefix("#/") {
+                return Ok(format!("{}{}", self.channel_prefix(), name));
+            }
+            // `#gps/times` のようにチャンネル名に `/` が含まれるので、最も長く一致するものを選ぶ
+            let channel = self
+                .channels
+                .iter()
+                .filter_map(|(raw, id)| {
+                    Some((key.strip_prefix(raw.as_str())?.strip_prefix('/')?, id))
+                })
+                .min_by_key(|(name, _)| name.len());
+            return match channel {
+                Some((name, id)) => Ok(format!("{}{}/{}", CHANNEL_PREFIX, id, name)),
+                // すでに保存するときの形になっているものは、このチャンネルの key だけを受け付ける
+                None if key[1..]
+                    .split_once('/')
+                    .is_some_and(|(id, _)| id == self.channel_id) =>
+                {
+                    Ok(key.to_string())
+                }
+                None if key[1..].split_once('/').is_some_and(|(id, _)| is_uuid(id)) => {
+                    Err(format!("Key \"{}\" belongs to another channel", key))
+                }
+                None => Err(format!("Channel of key \"{}\" is not found", key)),
+            };
+        }
+        Ok(key.to_string())
+    }
+
+    /// 範囲のない key を呼び出すときに探す順番
+    /// 自分の key、このチャンネルの key、全体の key の順に探す
+    pub fn lookup(&self, key: &str) -> Result<Vec<String>, String> {
+        if key.starts_with([USER_PREFIX, CHANNEL_PREFIX]) {
+            return Ok(vec![self.resolve(key)?]);
+        }
+        Ok(vec![
+            format!("{}{}", self.user_prefix(), key),
+            format!("{}{}", self.channel_prefix(), key),
+            key.to_string(),
+        ])
+    }
+
+    /// 保存されている key を、このユーザーとチャンネルから見た形で表示する
+    pub fn display(&self, stored: &str) -> String {
+        if let Some(name) = stored.strip_prefix(&self.user_prefix()) {
+            format!("{}me/{}", USER_PREFIX, name)
+        } else if let Some(name) = stored.strip_prefix(&self.channel_prefix()) {
+            format!("{}/{}", CHANNEL_PREFIX, name)
+        } else {
+            stored.to_string()
+        }
+    }
+
+    /// このユーザーとチャンネルから見える key の接頭辞
+    /// 範囲のない全体の key もすべて見える
+    pub fn visible_prefixes(&self) -> Vec<String> {
+        vec![self.user_prefix(), self.channel_prefix()]
+    }
+}
+
+/// コマンドで指定された key
+///
+/// 表示するときは入力されたままの形を使い、
+/// `Deref` で `&str` として使うときは保存するときの形を使う
+#[derive(Debug, Clone)]
+pub struct Key {
+    input: String,
+    stored: String,
+    lookup: Vec<String>,
+}
+
+impl Key {
+    pub fn new(input: impl Into<String>) -> Self {
+        let input = input.into();
+        Self {
+            stored: input.clone(),
+            lookup: vec![input.clone()],
+            input,
+        }
+    }
+
+    pub fn resolve(&mut self, scope: &Scope) -> Result<(), String> {
+        self.stored = scope.resolve(&self.input)?;
+        self.lookup = scope.lookup(&self.input)?;
+        Ok(())
+    }
+
+    /// 呼び出すときに探す key を、探す順に返す
+    pub fn lookup(&self) -> &[String] {
+        &self.lookup
+    }
+
+    /// ユーザーの key か
+    pub fn is_private(&self) -> bool {
+        self.stored.starts_with(USER_PREFIX)
+    }
+}
+
+impl Deref for Key {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.stored
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.input)
+    }
+}
+
+impl PartialEq<&str> for Key {
+    fn eq(&self, other: &&str) -> bool {
+        self.input == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "d352688f-a656-4444-8c5f-caa517e9ea1b";
+    const CHANNEL: &str = "0a0be82e-a9a1-4211-89c6-f7dbb0dced8c";
+    const OTHER_CHANNEL: &str = "6404a6b2-6f1e-4471-bcec-8352bcf0a83a";
+
+    fn scope() -> Scope {
+        Scope {
+            user_id: USER.to_string(),
+            channel_id: CHANNEL.to_string(),
+            channels: vec![("#gps/times".to_string(), OTHER_CHANNEL.to_string())],
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let scope = scope();
+        assert_eq!(scope.resolve("name"), Ok("name".to_string()));
+        assert_eq!(scope.resolve("~me/name"), Ok(format!("~{USER}/name")));
+        assert_eq!(scope.resolve("#/name"), Ok(format!("#{CHANNEL}/name")));
+        assert_eq!(
+            scope.resolve("#gps/times/name"),
+            Ok(format!("#{OTHER_CHANNEL}/name"))
+        );
+        assert_eq!(
+            scope.resolve(&format!("#{CHANNEL}/name")),
+            Ok(format!("#{CHANNEL}/name"))
+        );
+        assert!(scope.resolve(&format!("#{OTHER_CHANNEL}/name")).is_err());
+        assert!(scope.resolve(&format!("~{CHANNEL}/name")).is_err());
+        assert!(scope.resolve("#random/name").is_err());
+        assert!(scope.resolve("~me").is_err());
+    }
+
+    #[test]
+    fn test_lookup_and_display() {
+        let scope = scope();
+        let lookup = scope.lookup("name").unwrap();
+        assert_eq!(
+            lookup,
+            vec![
+                format!("~{USER}/name"),
+                format!("#{CHANNEL}/name"),
+                "name".to_string()
+            ]
+        );
+        assert_eq!(
+            lookup.iter().map(|k| scope.display(k)).collect::<Vec<_>>(),
+            vec!["~me/name", "#/name", "name"]
+        );
+        assert_eq!(scope.lookup("#/name").unwrap().len(), 1);
+    }
+}
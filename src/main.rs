@@ -1,12 +1,35 @@
+mod cli;
 mod config;
+mod explainer;
 mod generator;
 mod handler;
+mod matcher;
 mod model;
 mod parser;
+mod stats;
 
+use std::env;
+
+use sqlx::MySqlPool;
 use traq_ws_bot::builder;
 
-use crate::{config::BOT_ACCESS_TOKEN, model::db::connect_db};
+use crate::{
+    config::{BOT_ACCESS_TOKEN, STORE_BACKEND, TOMBSTONE_PURGE_INTERVAL, TOMBSTONE_RETENTION_DAYS},
+    model::{db, store},
+};
+
+/// 起動したときと、その後 `TOMBSTONE_PURGE_INTERVAL` ごとに、
+/// 保存しておく日数が過ぎた削除済みの key を完全に消して、新しく保存できるようにする
+async fn purge_tombstones_periodically(pool: MySqlPool) {
+    let mut interval = tokio::time::interval(*TOMBSTONE_PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match db::purge_tombstones(&pool, *TOMBSTONE_RETENTION_DAYS).await {
+            Ok(count) => log::debug!("Purged {} removed keys", count),
+            Err(e) => log::error!("Failed to purge removed keys: {:?}", e),
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
@@ -16,11 +39,29 @@ async fn main() {
     env_logger::init();
 
     log::debug!("Connecting to database...");
-    let pool = connect_db().await.unwrap();
+    let resource = store::connect(*STORE_BACKEND).await.unwrap();
     log::debug!("Connected to database");
 
+    // 引数がある場合は、BOT を起動せずにサブコマンドを実行する
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        let Some(pool) = &resource.pool else {
+            eprintln!("Error: subcommands are only available with STORE_BACKEND=mariadb");
+            std::process::exit(1);
+        };
+        if let Err(e) = cli::run(pool, &args).await {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(pool) = &resource.pool {
+        tokio::spawn(purge_tombstones_periodically(pool.clone()));
+    }
+
     let bot = builder(&*BOT_ACCESS_TOKEN)
-        .insert_resource(pool)
+        .insert_resource(resource)
         .on_message_created_with_resource(handler::on_message_created)
         .on_direct_message_created_with_resource(handler::on_direct_message_created)
         .build();
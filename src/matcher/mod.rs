@@ -0,0 +1,227 @@
+use crate::model::{Expression, Factor, Term, TermSuffix, Terms, Token};
+
+/// パターンが文字列を生成しうるかを調べる
+///
+/// `ends` は、`s` 中の開始位置の集合を受け取り、
+/// その位置から `s` と一致したまま生成を終えうる位置の集合を返す
+/// 位置の集合は、長さ `s.len() + 1` の `bool` の列で表す
+pub trait Match {
+    fn ends(&self, s: &[char], starts: &[bool]) -> Vec<bool>;
+}
+
+/// `expression` が `s` を生成しうるか
+pub fn is_match(expression: &Expression, s: &str) -> bool {
+    let s = s.chars().collect::<Vec<_>>();
+    expression.ends(&s, &start(s.len()))[s.len()]
+}
+
+/// `expression` の和集合のうち、`s` を生成しうるものの番号を返す
+pub fn matching_branches(expression: &Expression, s: &str) -> Vec<usize> {
+    let s = s.chars().collect::<Vec<_>>();
+    let starts = start(s.len());
+    match expression {
+        Expression::Union(terms) => terms
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.ends(&s, &starts)[s.len()])
+            .map(|(i, _)| i)
+            .collect(),
+    }
+}
+
+fn start(len: usize) -> Vec<bool> {
+    let mut starts = vec![false; len + 1];
+    starts[0] = true;
+    starts
+}
+
+fn union(acc: &mut [bool], v: &[bool]) {
+    for (a, x) in acc.iter_mut().zip(v) {
+        *a |= x;
+    }
+}
+
+impl Match for Token {
+    fn ends(&self, s: &[char], starts: &[bool]) -> Vec<bool> {
+        let mut res = vec![false; starts.len()];
+        for (i, _) in starts.iter().enumerate().filter(|(_, &b)| b) {
+            if i < s.len() && self.char_probability(s[i]) > 0.0 {
+                res[i + 1] = true;
+            }
+        }
+        res
+    }
+}
+
+impl Match for Factor {
+    fn ends(&self, s: &[char], starts: &[bool]) -> Vec<bool> {
+        match self {
+            Factor::Token(t) => t.ends(s, starts),
+            Factor::Group(e) | Factor::FixedGroup(e) => e.ends(s, starts),
+        }
+    }
+}
+
+/// 毎回独立に生成する繰り返しの `ends`
+///
+/// 繰り返すたびに位置は右に進むか、空文字列を生成してその場に留まるかのどちらかなので、
+/// `s.len() + 1` 回以上繰り返した後の集合はそれ以上変化しない
+///
+/// 入れ子になった繰り返しで回数が掛け合わされないように、集合が変化しなくなった時点で打ち切る
+fn repeat_ends(f: &Factor, suffix: &TermSuffix, s: &[char], starts: &[bool]) -> Vec<bool> {
+    let stable = s.len() + 1;
+    let min = suffix.min_count();
+    let max = suffix.max_count().unwrap_or(usize::MAX);
+    if min > max {
+        return vec![false; starts.len()];
+    }
+
+    let (min, max) = (min.min(stable), max.min(stable));
+    let mut res = vec![false; starts.len()];
+    let mut current = starts.to_vec();
+    for n in 0..=max {
+        if n >= min {
+            // `ends` は開始位置ごとの和なので、新しい位置が増えなければそれ以降も増えない
+            if is_subset(&current, &res) {
+                break;
+            }
+            union(&mut res, &current);
+        }
+        if n < max {
+            let next = f.ends(s, &current);
+            // 最小回数に達する前に変化しなくなった場合は、それ以降も同じ集合になる
+            if n < min && next == current {
+                union(&mut res, &current);
+                break;
+            }
+            current = next;
+        }
+    }
+    res
+}
+
+fn is_subset(v: &[bool], of: &[bool]) -> bool {
+    v.iter().zip(of).all(|(&x, &y)| !x || y)
+}
+
+/// 1 度だけ生成した文字列を繰り返す、固定グループの `ends`
+fn fixed_repeat_ends(f: &Factor, suffix: &TermSuffix, s: &[char], starts: &[bool]) -> Vec<bool> {
+    let min = suffix.min_count();
+    let max = suffix.max_count().unwrap_or(usize::MAX);
+    let mut res = vec![false; starts.len()];
+    if min > max {
+        return res;
+    }
+
+    for (i, _) in starts.iter().enumerate().filter(|(_, &b)| b) {
+        let mut single = vec![false; starts.len()];
+        single[i] = true;
+        let once = f.ends(s, &single);
+
+        // 0 回の繰り返しや、空文字列の繰り返しは空文字列になる
+        if min == 0 || once[i] {
+            res[i] = true;
+        }
+
+        for (k, _) in once.iter().enumerate().skip(i + 1).filter(|(_, &b)| b) {
+            let width = k - i;
+            let mut end = k;
+            let mut n = 1;
+            loop {
+                if n >= min {
+                    res[end] = true;
+                }
+                if n >= max || end + width > s.len() || s[end..end + width] != s[i..k] {
+                    break;
+                }
+                end += width;
+                n += 1;
+            }
+        }
+    }
+    res
+}
+
+impl Match for Term {
+    fn ends(&self, s: &[char], starts: &[bool]) -> Vec<bool> {
+        match self {
+            Term::Factor(f) => f.ends(s, starts),
+            Term::WithSuffix(f @ Factor::FixedGroup(_), suffix) => {
+                fixed_repeat_ends(f, suffix, s, starts)
+            }
+            Term::WithSuffix(f, suffix) => repeat_ends(f, suffix, s, starts),
+        }
+    }
+}
+
+impl Match for Terms {
+    fn ends(&self, s: &[char], starts: &[bool]) -> Vec<bool> {
+        match self {
+            Terms::Concat(t) => t
+                .iter()
+                .fold(starts.to_vec(), |starts, t| t.ends(s, &starts)),
+        }
+    }
+}
+
+impl Match for Expression {
+    fn ends(&self, s: &[char], starts: &[bool]) -> Vec<bool> {
+        match self {
+            Expression::Union(terms) => {
+                let mut res = vec![false; starts.len()];
+                for t in terms {
+                    union(&mut res, &t.ends(s, starts));
+                }
+                res
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn matches(pattern: &str, s: &str) -> bool {
+        is_match(&parse(pattern).unwrap(), s)
+    }
+
+    #[test]
+    fn test_is_match() {
+        assert!(matches("[a-z]{3}[A-Z]", "abcD"));
+        assert!(!matches("[a-z]{3}[A-Z]", "abD"));
+        assert!(matches("表*裏", "裏"));
+        assert!(matches("表*裏", "表表表裏"));
+        assert!(!matches("表+裏", "裏"));
+        assert!(matches("(a|bc){2,}", "abcbc"));
+        assert!(matches("(a?){10000}", ""));
+        assert!(!matches(r"\q", "q"));
+    }
+
+    #[test]
+    fn test_is_match_nested_repeat() {
+        let s = "a".repeat(200);
+        assert!(!matches("((((((a*)*)*)*)*)*)b", &s));
+        assert!(matches("((((((a*)*)*)*)*)*)", &s));
+        assert!(matches("(((a{0}){10000}){10000})", ""));
+        assert!(!matches("((a{2}){3,}){2}", &"a".repeat(10)));
+        assert!(matches("((a{2}){3,}){2}", &"a".repeat(12)));
+    }
+
+    #[test]
+    fn test_is_match_fixed_group() {
+        assert!(matches("<[a-z]>{2}", "cc"));
+        assert!(!matches("<[a-z]>{2}", "ac"));
+        assert!(matches("<a|bc>+d", "bcbcd"));
+        assert!(!matches("<a|bc>+d", "abcd"));
+        assert!(matches("<a?>{3}", ""));
+    }
+
+    #[test]
+    fn test_matching_branches() {
+        let e = parse("hoge|h[a-z]ge|fuga").unwrap();
+        assert_eq!(matching_branches(&e, "hoge"), vec![0, 1]);
+        assert_eq!(matching_branches(&e, "piyo"), Vec::<usize>::new());
+    }
+}
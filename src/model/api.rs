@@ -1,15 +1,21 @@
 use std::str::FromStr;
 
+use reqwest::multipart::{Form, Part};
 use traq_ws_bot::{
     openapi::{
         self,
-        models::{PostBotActionJoinRequest, PostBotActionLeaveRequest, PostMessageRequest},
+        models::{
+            FileInfo, PostBotActionJoinRequest, PostBotActionLeaveRequest, PostMessageRequest,
+        },
     },
     utils::create_configuration,
 };
 
 use crate::config::{BOT_ACCESS_TOKEN, BOT_ID};
 
+/// `download_file` で読み込むファイルの最大のバイト数
+const DOWNLOAD_SIZE_LIMIT: usize = 1024 * 1024;
+
 pub async fn send_message(channel_id: &str, text: &str, emb: bool) -> anyhow::Result<()> {
     let configuration = create_configuration(&*BOT_ACCESS_TOKEN);
     let res = openapi::apis::message_api::post_message(
@@ -54,3 +60,76 @@ pub async fn leave_channel(channel_id: &str) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// ファイルをチャンネルにアップロードし、メッセージに貼るための URL を返す
+///
+/// 生成された `post_file` はファイルを送らないので、直接リクエストする
+pub async fn upload_file(
+    channel_id: &str,
+    file_name: &str,
+    mime_type: &str,
+    content: String,
+) -> anyhow::Result<String> {
+    let configuration = create_configuration(&*BOT_ACCESS_TOKEN);
+    let file = Part::text(content)
+        .file_name(file_name.to_string())
+        .mime_str(mime_type)?;
+    let form = Form::new()
+        .text("channelId", channel_id.to_string())
+        .part("file", file);
+    let res = configuration
+        .client
+        .post(format!("{}/files", configuration.base_path))
+        .bearer_auth(&*BOT_ACCESS_TOKEN)
+        .multipart(form)
+        .send()
+        .await?
+        .error_for_status()?;
+    let info: FileInfo = serde_json::from_str(&res.text().await?)?;
+
+    Ok(file_url(&configuration.base_path, &info.id.to_string()))
+}
+
+/// アップロードされたファイルの中身を文字列として読む
+///
+/// 生成された `get_file` は中身を JSON として読もうとするので、直接リクエストする
+/// 大きすぎるファイルは、メモリに読み込む前に断る
+pub async fn download_file(file_id: &str) -> anyhow::Result<String> {
+    let configuration = create_configuration(&*BOT_ACCESS_TOKEN);
+    let mut res = configuration
+        .client
+        .get(format!("{}/files/{}", configuration.base_path, file_id))
+        .bearer_auth(&*BOT_ACCESS_TOKEN)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let too_large = || {
+        anyhow::anyhow!(
+            "ファイルは {} バイト以内にしてください",
+            DOWNLOAD_SIZE_LIMIT
+        )
+    };
+    if res
+        .content_length()
+        .is_some_and(|length| length > DOWNLOAD_SIZE_LIMIT as u64)
+    {
+        return Err(too_large());
+    }
+    // Content-Length がないか正しくない場合に備えて、読みながらも確かめる
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        if body.len() + chunk.len() > DOWNLOAD_SIZE_LIMIT {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(String::from_utf8(body)?)
+}
+
+/// `https://q.trap.jp/api/v3` から `https://q.trap.jp/files/{id}` を作る
+fn file_url(base_path: &str, file_id: &str) -> String {
+    let origin = base_path.trim_end_matches("/api/v3");
+    format!("{}/files/{}", origin, file_id)
+}
@@ -1,8 +1,15 @@
 use std::env;
 
-use sqlx::{mysql::MySqlPoolOptions, MySqlPool};
+use rand::Rng;
+use sqlx::{
+    mysql::MySqlPoolOptions, types::chrono::NaiveDateTime, MySql, MySqlConnection, MySqlPool,
+    QueryBuilder,
+};
+
+use super::{dump, key};
 
 /// 環境変数を用いて、db に接続する
+/// 接続したら `migrations/` のうちまだ適用していないものを適用する
 pub async fn connect_db() -> anyhow::Result<MySqlPool> {
     dotenv::dotenv().ok();
     let hostname = env::var("MARIADB_HOSTNAME").unwrap();
@@ -17,65 +24,1529 @@ pub async fn connect_db() -> anyhow::Result<MySqlPool> {
             username, password, hostname, database
         ))
         .await?;
+
+    sqlx::migrate!("./migrations").run(&pool).await?;
+
     Ok(pool)
 }
 
+/// `/save` や `/update` でつける説明とタグ
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub description: Option<String>,
+    /// `None` の場合は、`/update` でタグを書き換えない
+    /// `Some(vec![])` の場合は、タグをすべて外す
+    pub tags: Option<Vec<String>>,
+}
+
 pub async fn save(
     pool: &MySqlPool,
     key: &str,
     value: &str,
+    metadata: &Metadata,
     user_id: &str,
     user_name: &str,
 ) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+
     let query = r#"
-        INSERT INTO `regexps` (`key`, `regexp`, `user_id`, `user_name`) VALUES (?, ?, ?, ?)
+        INSERT INTO `regexps` (`key`, `regexp`, `description`, `user_id`, `user_name`)
+        VALUES (?, ?, ?, ?, ?)
     "#;
 
     sqlx::query(query)
         .bind(key)
         .bind(value)
+        .bind(&metadata.description)
         .bind(user_id)
         .bind(user_name)
-        .execute(pool)
+        .execute(&mut *tx)
+        .await?;
+
+    set_tags(&mut tx, key, metadata.tags.as_deref().unwrap_or_default()).await?;
+    add_revision(&mut tx, key, Action::Save, value, user_id, user_name).await?;
+
+    tx.commit().await
+}
+
+/// 保存された正規表現を書き換える
+/// 説明とタグは、指定された場合だけ書き換える
+/// 書き換えた場合は `true` を、key が見つからなかった場合は `false` を返す
+pub async fn update(
+    pool: &MySqlPool,
+    key: &str,
+    value: &str,
+    metadata: &Metadata,
+    user_id: &str,
+    user_name: &str,
+) -> sqlx::Result<bool> {
+    let query = r#"
+        UPDATE `regexps`
+        SET `regexp` = ?, `description` = COALESCE(?, `description`),
+            `updated_at` = CURRENT_TIMESTAMP
+        WHERE `key` = ? AND `deleted_at` IS NULL
+    "#;
+
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(query)
+        .bind(value)
+        .bind(&metadata.description)
+        .bind(key)
+        .execute(&mut *tx)
         .await?;
 
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    if let Some(tags) = &metadata.tags {
+        set_tags(&mut tx, key, tags).await?;
+    }
+    add_revision(&mut tx, key, Action::Update, value, user_id, user_name).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// key のタグをすべて `tags` に置き換える
+async fn set_tags(conn: &mut MySqlConnection, key: &str, tags: &[String]) -> sqlx::Result<()> {
+    let query = r#"
+        DELETE FROM `regexp_tags` WHERE `key` = ?
+    "#;
+
+    sqlx::query(query).bind(key).execute(&mut *conn).await?;
+
+    if tags.is_empty() {
+        return Ok(());
+    }
+    let mut builder = QueryBuilder::new("INSERT IGNORE INTO `regexp_tags` (`key`, `tag`) ");
+    builder.push_values(tags, |mut b, tag| {
+        b.push_bind(key).push_bind(tag);
+    });
+    builder.build().execute(conn).await?;
+
     Ok(())
 }
 
+/// key につけられたタグを返す
+pub async fn tags(pool: &MySqlPool, key: &str) -> sqlx::Result<Vec<String>> {
+    let query = r#"
+        SELECT `tag` FROM `regexp_tags` WHERE `key` = ? ORDER BY `tag`
+    "#;
+
+    let rows: Vec<(String,)> = sqlx::query_as(query).bind(key).fetch_all(pool).await?;
+
+    Ok(rows.into_iter().map(|(tag,)| tag).collect())
+}
+
 #[derive(sqlx::FromRow, Debug)]
 pub struct Count {
     pub count: i64,
 }
-pub async fn remove(pool: &MySqlPool, key: &str, user_id: &str) -> sqlx::Result<bool> {
+/// 削除した場合は `true` を、key が見つからなかった場合は `false` を返す
+pub async fn remove(
+    pool: &MySqlPool,
+    key: &str,
+    user_id: &str,
+    user_name: &str,
+) -> sqlx::Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    // 削除した正規表現も履歴から戻せるように、削除する前に取得しておく
+    // 削除し終わるまで、他の操作で書き換えられないようにロックする
     let query = r#"
-        DELETE FROM `regexps` WHERE `key` = ? AND `user_id` = ?
+        SELECT `regexp` FROM `regexps` WHERE `key` = ? AND `deleted_at` IS NULL FOR UPDATE
     "#;
 
-    sqlx::query(query)
+    let row: Option<Regexp> = sqlx::query_as(query)
         .bind(key)
-        .bind(user_id)
-        .execute(pool)
+        .fetch_optional(&mut *tx)
         .await?;
+    let Some(row) = row else {
+        return Ok(false);
+    };
 
+    // 復元できるように、すぐには消さずに削除した日時を記録する
     let query = r#"
-        SELECT ROW_COUNT() AS `count`
+        UPDATE `regexps` SET `deleted_at` = CURRENT_TIMESTAMP
+        WHERE `key` = ? AND `deleted_at` IS NULL
     "#;
 
-    let row: Count = sqlx::query_as(query).fetch_one(pool).await?;
+    let result = sqlx::query(query).bind(key).execute(&mut *tx).await?;
 
-    Ok(row.count > 0)
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    add_revision(
+        &mut tx,
+        key,
+        Action::Delete,
+        &row.regexp,
+        user_id,
+        user_name,
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// `candidates` のうち、保存されている最初の key を返す
+/// 別名が見つかった場合は、別名をつけられた key を返す
+pub async fn find_key(pool: &MySqlPool, candidates: &[String]) -> sqlx::Result<Option<String>> {
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder =
+        QueryBuilder::new("SELECT `key` AS `name`, `key` FROM `regexps` WHERE `key` IN (");
+    let mut separated = builder.separated(", ");
+    for candidate in candidates {
+        separated.push_bind(candidate);
+    }
+    builder.push(") AND `deleted_at` IS NULL");
+    builder.push(
+        " UNION ALL SELECT `a`.`alias`, `a`.`key` FROM `regexp_aliases` AS `a` \
+        JOIN `regexps` AS `r` ON `r`.`key` = `a`.`key` WHERE `a`.`alias` IN (",
+    );
+    let mut separated = builder.separated(", ");
+    for candidate in candidates {
+        separated.push_bind(candidate);
+    }
+    builder.push(") AND `r`.`deleted_at` IS NULL");
+    let rows: Vec<(String, String)> = builder.build_query_as().fetch_all(pool).await?;
+
+    // 範囲の違う key への別名は、他の人の key を読めてしまうので使わない
+    Ok(candidates.iter().find_map(|c| {
+        rows.iter()
+            .find(|(name, key)| name == c && key::same_scope(name, key))
+            .map(|(_, key)| key.clone())
+    }))
 }
 
 #[derive(sqlx::FromRow, Debug)]
 pub struct Regexp {
     pub regexp: String,
 }
+/// 別名は解決しないので、別名も探す場合は `find_key` を使う
 pub async fn get(pool: &MySqlPool, key: &str) -> sqlx::Result<Option<String>> {
     let query = r#"
-        SELECT `regexp` FROM `regexps` WHERE `key` = ?
+        SELECT `regexp` FROM `regexps` WHERE `key` = ? AND `deleted_at` IS NULL
     "#;
 
     let row: Option<Regexp> = sqlx::query_as(query).bind(key).fetch_optional(pool).await?;
 
     Ok(row.map(|row| row.regexp))
 }
+
+/// `list` で絞り込む条件
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    pub user_id: Option<String>,
+    pub user_name: Option<String>,
+    pub prefix: Option<String>,
+    pub tag: Option<String>,
+    /// このユーザーのお気に入りだけに絞り込む
+    pub favorite_of: Option<String>,
+    /// チャンネルやユーザーの key のうち、見えるものの接頭辞
+    pub visible_prefixes: Vec<String>,
+}
+
+impl ListFilter {
+    fn push_conditions<'a>(&'a self, builder: &mut QueryBuilder<'a, MySql>) {
+        builder.push(" WHERE `deleted_at` IS NULL");
+        builder.push(" AND (`key` NOT LIKE '#%' AND `key` NOT LIKE '~%'");
+        for prefix in &self.visible_prefixes {
+            builder
+                .push(" OR `key` LIKE ")
+                .push_bind(format!("{}%", escape_like(prefix)));
+        }
+        builder.push(")");
+        if let Some(user_id) = &self.user_id {
+            builder.push(" AND `user_id` = ").push_bind(user_id);
+        }
+        if let Some(user_name) = &self.user_name {
+            builder.push(" AND `user_name` = ").push_bind(user_name);
+        }
+        if let Some(prefix) = &self.prefix {
+            builder
+                .push(" AND `key` LIKE ")
+                .push_bind(format!("{}%", escape_like(prefix)));
+        }
+        if let Some(tag) = &self.tag {
+            builder
+                .push(" AND `key` IN (SELECT `key` FROM `regexp_tags` WHERE `tag` = ")
+                .push_bind(tag)
+                .push(")");
+        }
+        if let Some(user_id) = &self.favorite_of {
+            builder
+                .push(" AND `key` IN (SELECT `key` FROM `regexp_favorites` WHERE `user_id` = ")
+                .push_bind(user_id)
+                .push(")");
+        }
+    }
+}
+
+/// LIKE のパターン中で特別な意味を持つ文字をエスケープする
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct Entry {
+    pub key: String,
+    pub regexp: String,
+    pub user_name: String,
+}
+
+#[derive(Debug)]
+pub struct Page {
+    pub entries: Vec<Entry>,
+    pub total: i64,
+}
+
+pub async fn list(
+    pool: &MySqlPool,
+    filter: &ListFilter,
+    limit: i64,
+    offset: i64,
+) -> sqlx::Result<Page> {
+    let mut builder = QueryBuilder::new("SELECT COUNT(*) AS `count` FROM `regexps`");
+    filter.push_conditions(&mut builder);
+    let count: Count = builder.build_query_as().fetch_one(pool).await?;
+
+    let mut builder = QueryBuilder::new("SELECT `key`, `regexp`, `user_name` FROM `regexps`");
+    filter.push_conditions(&mut builder);
+    builder
+        .push(" ORDER BY `key` LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let entries = builder.build_query_as().fetch_all(pool).await?;
+
+    Ok(Page {
+        entries,
+        total: count.count,
+    })
+}
+
+/// `filter` に一致するものから 1 つを無作為に選ぶ
+///
+/// `ORDER BY RAND()` や `COUNT(*)` と `OFFSET` は一致するものをすべて読むので、
+/// 一致するものの `id` の範囲から無作為に選んだ `id` 以上で、最初に一致するものをインデックスを使って読む
+/// 選んでいる間に消された場合に備えて、見つからなければ先頭に戻って、選んだ `id` より前から探す
+/// 削除や条件に一致しないもので `id` に隙間があると、その直後のものが選ばれやすくなる
+pub async fn random_entry(pool: &MySqlPool, filter: &ListFilter) -> sqlx::Result<Option<Entry>> {
+    let mut builder = QueryBuilder::new("SELECT MIN(`id`), MAX(`id`) FROM `regexps`");
+    filter.push_conditions(&mut builder);
+
+    let bounds: (Option<u32>, Option<u32>) = builder.build_query_as().fetch_one(pool).await?;
+    let (Some(min_id), Some(max_id)) = bounds else {
+        return Ok(None);
+    };
+    let start = rand::thread_rng().gen_range(min_id..=max_id);
+
+    for (condition, id) in [(" AND `id` >= ", start), (" AND `id` < ", start)] {
+        let mut builder = QueryBuilder::new("SELECT `key`, `regexp`, `user_name` FROM `regexps`");
+        filter.push_conditions(&mut builder);
+        builder
+            .push(condition)
+            .push_bind(id)
+            .push(" ORDER BY `id` LIMIT 1");
+        let entry = builder.build_query_as().fetch_optional(pool).await?;
+        if entry.is_some() {
+            return Ok(entry);
+        }
+    }
+    Ok(None)
+}
+
+/// key と正規表現と説明から `words` を探し、よく一致するものから順に返す
+///
+/// FULLTEXT インデックスで探し、見つからなかった場合は LIKE で探し直す
+/// FULLTEXT では短い単語や記号が無視されるので、正規表現の一部で探す場合は LIKE で見つかることが多い
+pub async fn search(
+    pool: &MySqlPool,
+    filter: &ListFilter,
+    words: &[String],
+    limit: i64,
+) -> sqlx::Result<Vec<Entry>> {
+    match search_fulltext(pool, filter, words, limit).await {
+        Ok(entries) if !entries.is_empty() => return Ok(entries),
+        Ok(_) => {}
+        Err(e) => log::warn!("Failed to search with FULLTEXT index: {:?}", e),
+    }
+    search_like(pool, filter, words, limit).await
+}
+
+async fn search_fulltext(
+    pool: &MySqlPool,
+    filter: &ListFilter,
+    words: &[String],
+    limit: i64,
+) -> sqlx::Result<Vec<Entry>> {
+    let words = words.join(" ");
+    let mut builder = QueryBuilder::new("SELECT `key`, `regexp`, `user_name` FROM `regexps`");
+    filter.push_conditions(&mut builder);
+    builder
+        .push(" AND MATCH (`key`, `regexp`, `description`) AGAINST (")
+        .push_bind(&words)
+        .push(" IN NATURAL LANGUAGE MODE)")
+        .push(" ORDER BY MATCH (`key`, `regexp`, `description`) AGAINST (")
+        .push_bind(&words)
+        .push(" IN NATURAL LANGUAGE MODE) DESC, `key` LIMIT ")
+        .push_bind(limit);
+    builder.build_query_as().fetch_all(pool).await
+}
+
+/// 単語ごとに、key と完全に一致すれば 4 点、key の先頭と一致すれば 2 点、
+/// key に含まれていれば 2 点、正規表現や説明に含まれていれば 1 点として、点数の高い順に返す
+async fn search_like(
+    pool: &MySqlPool,
+    filter: &ListFilter,
+    words: &[String],
+    limit: i64,
+) -> sqlx::Result<Vec<Entry>> {
+    let mut builder = QueryBuilder::new("SELECT `key`, `regexp`, `user_name` FROM `regexps`");
+    filter.push_conditions(&mut builder);
+    builder.push(" AND (1 = 0");
+    for word in words {
+        let contains = format!("%{}%", escape_like(word));
+        builder
+            .push(" OR `key` LIKE ")
+            .push_bind(contains.clone())
+            .push(" OR `regexp` LIKE ")
+            .push_bind(contains.clone())
+            .push(" OR `description` LIKE ")
+            .push_bind(contains);
+    }
+    builder.push(") ORDER BY (0");
+    for word in words {
+        let escaped = escape_like(word);
+        builder
+            .push(" + 4 * (`key` = ")
+            .push_bind(word)
+            .push(") + 2 * (`key` LIKE ")
+            .push_bind(format!("{}%", escaped))
+            .push(") + 2 * (`key` LIKE ")
+            .push_bind(format!("%{}%", escaped))
+            .push(") + (`regexp` LIKE ")
+            .push_bind(format!("%{}%", escaped))
+            .push(") + COALESCE(`description` LIKE ")
+            .push_bind(format!("%{}%", escaped))
+            .push(", 0)");
+    }
+    builder.push(") DESC, `key` LIMIT ").push_bind(limit);
+    builder.build_query_as().fetch_all(pool).await
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct Info {
+    pub key: String,
+    pub regexp: String,
+    pub description: Option<String>,
+    pub user_name: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    pub call_count: u32,
+}
+pub async fn get_info(pool: &MySqlPool, key: &str) -> sqlx::Result<Option<Info>> {
+    let query = r#"
+        SELECT `key`, `regexp`, `description`, `user_name`, `created_at`, `updated_at`,
+            `call_count`
+        FROM `regexps` WHERE `key` = ? AND `deleted_at` IS NULL
+    "#;
+
+    sqlx::query_as(query).bind(key).fetch_optional(pool).await
+}
+
+/// `/call` で呼び出された回数を数える
+pub async fn increment_call_count(pool: &MySqlPool, key: &str) -> sqlx::Result<()> {
+    let query = r#"
+        UPDATE `regexps` SET `call_count` = `call_count` + 1
+        WHERE `key` = ? AND `deleted_at` IS NULL
+    "#;
+
+    sqlx::query(query).bind(key).execute(pool).await?;
+
+    Ok(())
+}
+
+/// `/call` で呼び出されたことを、呼び出した人とチャンネルとともに記録する
+pub async fn record_call(
+    pool: &MySqlPool,
+    key: &str,
+    user_id: &str,
+    user_name: &str,
+    channel_id: &str,
+) -> sqlx::Result<()> {
+    let query = r#"
+        INSERT INTO `regexp_calls` (`key`, `user_id`, `user_name`, `channel_id`)
+        VALUES (?, ?, ?, ?)
+    "#;
+
+    sqlx::query(query)
+        .bind(key)
+        .bind(user_id)
+        .bind(user_name)
+        .bind(channel_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// 呼び出しの記録を数える範囲
+#[derive(Debug, Clone, Default)]
+pub struct UsageFilter {
+    /// 直近の何日間か。`None` の場合はすべての期間
+    pub days: Option<u32>,
+    /// 呼び出した人の ID
+    pub user_id: Option<String>,
+}
+
+impl UsageFilter {
+    fn push_conditions<'a>(&'a self, builder: &mut QueryBuilder<'a, MySql>) {
+        builder.push(" WHERE 1 = 1");
+        if let Some(days) = self.days {
+            builder
+                .push(" AND `created_at` >= CURRENT_TIMESTAMP - INTERVAL ")
+                .push_bind(days)
+                .push(" DAY");
+        }
+        if let Some(user_id) = &self.user_id {
+            builder.push(" AND `user_id` = ").push_bind(user_id);
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct Usage {
+    pub name: String,
+    pub count: i64,
+}
+
+/// よく呼び出された key を、呼び出された回数の多い順に返す
+/// `filter` に一致する、削除されていない key だけを数える
+pub async fn key_ranking(
+    pool: &MySqlPool,
+    usage: &UsageFilter,
+    filter: &ListFilter,
+    limit: i64,
+) -> sqlx::Result<Vec<Usage>> {
+    let mut builder =
+        QueryBuilder::new("SELECT `key` AS `name`, COUNT(*) AS `count` FROM `regexp_calls`");
+    usage.push_conditions(&mut builder);
+    builder.push(" AND `key` IN (SELECT `key` FROM `regexps`");
+    filter.push_conditions(&mut builder);
+    builder
+        .push(") GROUP BY `key` ORDER BY `count` DESC, `key` LIMIT ")
+        .push_bind(limit);
+    builder.build_query_as().fetch_all(pool).await
+}
+
+/// よく呼び出した人を、呼び出した回数の多い順に返す
+pub async fn user_ranking(
+    pool: &MySqlPool,
+    usage: &UsageFilter,
+    limit: i64,
+) -> sqlx::Result<Vec<Usage>> {
+    let mut builder = QueryBuilder::new(
+        "SELECT MAX(`user_name`) AS `name`, COUNT(*) AS `count` FROM `regexp_calls`",
+    );
+    usage.push_conditions(&mut builder);
+    builder
+        .push(" GROUP BY `user_id` ORDER BY `count` DESC, `name` LIMIT ")
+        .push_bind(limit);
+    builder.build_query_as().fetch_all(pool).await
+}
+
+/// 呼び出した回数を返す
+pub async fn count_calls(pool: &MySqlPool, usage: &UsageFilter) -> sqlx::Result<i64> {
+    let mut builder = QueryBuilder::new("SELECT COUNT(*) AS `count` FROM `regexp_calls`");
+    usage.push_conditions(&mut builder);
+    let count: Count = builder.build_query_as().fetch_one(pool).await?;
+
+    Ok(count.count)
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct OwnerStats {
+    /// 保存している key の数
+    pub keys: i64,
+    /// 保存している key が呼び出された回数の合計
+    pub calls: i64,
+}
+
+/// `user_id` が保存している、削除されていない key についての統計を返す
+pub async fn owner_stats(pool: &MySqlPool, user_id: &str) -> sqlx::Result<OwnerStats> {
+    let query = r#"
+        SELECT COUNT(*) AS `keys`, CAST(COALESCE(SUM(`call_count`), 0) AS SIGNED) AS `calls`
+        FROM `regexps` WHERE `user_id` = ? AND `deleted_at` IS NULL
+    "#;
+
+    sqlx::query_as(query).bind(user_id).fetch_one(pool).await
+}
+
+/// 履歴に記録される操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Save,
+    Update,
+    Delete,
+    Restore,
+    Rollback,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Save => "save",
+            Action::Update => "update",
+            Action::Delete => "delete",
+            Action::Restore => "restore",
+            Action::Rollback => "rollback",
+        }
+    }
+}
+
+/// key ごとに 1 から順に番号をつけて、履歴を記録する
+async fn add_revision(
+    conn: &mut MySqlConnection,
+    key: &str,
+    action: Action,
+    value: &str,
+    user_id: &str,
+    user_name: &str,
+) -> sqlx::Result<()> {
+    let query = r#"
+        INSERT INTO `regexp_revisions` (`key`, `revision`, `action`, `regexp`, `user_id`, `user_name`)
+        SELECT ?, COALESCE(MAX(`revision`), 0) + 1, ?, ?, ?, ?
+        FROM `regexp_revisions` WHERE `key` = ?
+    "#;
+
+    sqlx::query(query)
+        .bind(key)
+        .bind(action.as_str())
+        .bind(value)
+        .bind(user_id)
+        .bind(user_name)
+        .bind(key)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct Revision {
+    pub revision: u32,
+    pub action: String,
+    pub regexp: String,
+    pub user_name: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// 新しいものから順に、最大 `limit` 件の履歴を返す
+pub async fn history(pool: &MySqlPool, key: &str, limit: i64) -> sqlx::Result<Vec<Revision>> {
+    let query = r#"
+        SELECT `revision`, `action`, `regexp`, `user_name`, `created_at`
+        FROM `regexp_revisions` WHERE `key` = ? ORDER BY `revision` DESC LIMIT ?
+    "#;
+
+    sqlx::query_as(query)
+        .bind(key)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_revision(
+    pool: &MySqlPool,
+    key: &str,
+    revision: u32,
+) -> sqlx::Result<Option<Revision>> {
+    let query = r#"
+        SELECT `revision`, `action`, `regexp`, `user_name`, `created_at`
+        FROM `regexp_revisions` WHERE `key` = ? AND `revision` = ?
+    "#;
+
+    sqlx::query_as(query)
+        .bind(key)
+        .bind(revision)
+        .fetch_optional(pool)
+        .await
+}
+
+/// key を `revision` の正規表現に戻す
+/// 削除されている場合は復元もする
+pub async fn rollback(
+    pool: &MySqlPool,
+    key: &str,
+    revision: &Revision,
+    user_id: &str,
+    user_name: &str,
+) -> sqlx::Result<()> {
+    let query = r#"
+        UPDATE `regexps`
+        SET `regexp` = ?, `updated_at` = CURRENT_TIMESTAMP, `deleted_at` = NULL
+        WHERE `key` = ?
+    "#;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(query)
+        .bind(&revision.regexp)
+        .bind(key)
+        .execute(&mut *tx)
+        .await?;
+
+    add_revision(
+        &mut tx,
+        key,
+        Action::Rollback,
+        &revision.regexp,
+        user_id,
+        user_name,
+    )
+    .await?;
+
+    tx.commit().await
+}
+
+/// 削除された key を復元する
+pub async fn restore(
+    pool: &MySqlPool,
+    key: &str,
+    user_id: &str,
+    user_name: &str,
+) -> sqlx::Result<bool> {
+    let query = r#"
+        UPDATE `regexps` SET `deleted_at` = NULL
+        WHERE `key` = ? AND `deleted_at` IS NOT NULL
+    "#;
+
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(query).bind(key).execute(&mut *tx).await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let query = r#"
+        SELECT `regexp` FROM `regexps` WHERE `key` = ?
+    "#;
+
+    let row: Regexp = sqlx::query_as(query).bind(key).fetch_one(&mut *tx).await?;
+    add_revision(
+        &mut tx,
+        key,
+        Action::Restore,
+        &row.regexp,
+        user_id,
+        user_name,
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// key が削除されている場合は、削除された日時を返す
+pub async fn deleted_at(pool: &MySqlPool, key: &str) -> sqlx::Result<Option<NaiveDateTime>> {
+    let query = r#"
+        SELECT `deleted_at` FROM `regexps` WHERE `key` = ? AND `deleted_at` IS NOT NULL
+    "#;
+
+    let row: Option<(NaiveDateTime,)> =
+        sqlx::query_as(query).bind(key).fetch_optional(pool).await?;
+
+    Ok(row.map(|(deleted_at,)| deleted_at))
+}
+
+/// 削除されてから `retention_days` 日以上経ったものを完全に消し、消した数を返す
+/// 管理者にロックされたものは、同じ key で保存し直せないように残しておく
+pub async fn purge_tombstones(pool: &MySqlPool, retention_days: u32) -> sqlx::Result<u64> {
+    let query = r#"
+        DELETE FROM `regexps`
+        WHERE `deleted_at` IS NOT NULL AND `deleted_at` < CURRENT_TIMESTAMP - INTERVAL ? DAY
+            AND NOT `locked`
+    "#;
+
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(query)
+        .bind(retention_days)
+        .execute(&mut *tx)
+        .await?;
+
+    // 新しく同じ key で保存した人に、共同管理者が引き継がれないようにする
+    let query = r#"
+        DELETE FROM `regexp_maintainers` WHERE `key` NOT IN (SELECT `key` FROM `regexps`)
+    "#;
+
+    sqlx::query(query).execute(&mut *tx).await?;
+
+    let query = r#"
+        DELETE FROM `regexp_aliases` WHERE `key` NOT IN (SELECT `key` FROM `regexps`)
+    "#;
+
+    sqlx::query(query).execute(&mut *tx).await?;
+
+    let query = r#"
+        DELETE FROM `regexp_tags` WHERE `key` NOT IN (SELECT `key` FROM `regexps`)
+    "#;
+
+    sqlx::query(query).execute(&mut *tx).await?;
+
+    let query = r#"
+        DELETE FROM `regexp_calls` WHERE `key` NOT IN (SELECT `key` FROM `regexps`)
+    "#;
+
+    sqlx::query(query).execute(&mut *tx).await?;
+
+    let query = r#"
+        DELETE FROM `regexp_favorites` WHERE `key` NOT IN (SELECT `key` FROM `regexps`)
+    "#;
+
+    sqlx::query(query).execute(&mut *tx).await?;
+
+    // 新しく同じ key で保存した人に、前の履歴が見えたり戻せたりしないようにする
+    let query = r#"
+        DELETE FROM `regexp_revisions` WHERE `key` NOT IN (SELECT `key` FROM `regexps`)
+    "#;
+
+    sqlx::query(query).execute(&mut *tx).await?;
+
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// 削除されたものも含めて、key を保存した人の ID を返す
+pub async fn get_owner(pool: &MySqlPool, key: &str) -> sqlx::Result<Option<String>> {
+    let query = r#"
+        SELECT `user_id` FROM `regexps` WHERE `key` = ?
+    "#;
+
+    let row: Option<(String,)> = sqlx::query_as(query).bind(key).fetch_optional(pool).await?;
+
+    Ok(row.map(|(user_id,)| user_id))
+}
+
+/// key を保存した人を `user_id` に変更する
+pub async fn transfer(
+    pool: &MySqlPool,
+    key: &str,
+    user_id: &str,
+    user_name: &str,
+) -> sqlx::Result<bool> {
+    let query = r#"
+        UPDATE `regexps` SET `user_id` = ?, `user_name` = ?
+        WHERE `key` = ? AND `deleted_at` IS NULL
+    "#;
+
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(query)
+        .bind(user_id)
+        .bind(user_name)
+        .bind(key)
+        .execute(&mut *tx)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    // 保存した人になったので、共同管理者からは外す
+    let query = r#"
+        DELETE FROM `regexp_maintainers` WHERE `key` = ? AND `user_id` = ?
+    "#;
+
+    sqlx::query(query)
+        .bind(key)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn is_maintainer(pool: &MySqlPool, key: &str, user_id: &str) -> sqlx::Result<bool> {
+    let query = r#"
+        SELECT COUNT(*) AS `count` FROM `regexp_maintainers` WHERE `key` = ? AND `user_id` = ?
+    "#;
+
+    let row: Count = sqlx::query_as(query)
+        .bind(key)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(row.count > 0)
+}
+
+/// 共同管理者の名前を返す
+pub async fn maintainers(pool: &MySqlPool, key: &str) -> sqlx::Result<Vec<String>> {
+    let query = r#"
+        SELECT `user_name` FROM `regexp_maintainers` WHERE `key` = ? ORDER BY `created_at`
+    "#;
+
+    let rows: Vec<(String,)> = sqlx::query_as(query).bind(key).fetch_all(pool).await?;
+
+    Ok(rows.into_iter().map(|(user_name,)| user_name).collect())
+}
+
+/// 共同管理者を追加する
+/// 追加した場合は `true` を、すでに共同管理者だった場合は `false` を返す
+pub async fn add_maintainer(
+    pool: &MySqlPool,
+    key: &str,
+    user_id: &str,
+    user_name: &str,
+) -> sqlx::Result<bool> {
+    let query = r#"
+        INSERT IGNORE INTO `regexp_maintainers` (`key`, `user_id`, `user_name`) VALUES (?, ?, ?)
+    "#;
+
+    let result = sqlx::query(query)
+        .bind(key)
+        .bind(user_id)
+        .bind(user_name)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn remove_maintainer(pool: &MySqlPool, key: &str, user_id: &str) -> sqlx::Result<bool> {
+    let query = r#"
+        DELETE FROM `regexp_maintainers` WHERE `key` = ? AND `user_id` = ?
+    "#;
+
+    let result = sqlx::query(query)
+        .bind(key)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// 管理者が key をロックする、またはロックを解除する
+/// 削除されたものも対象にし、key が見つからなかった場合は `false` を返す
+pub async fn set_locked(pool: &MySqlPool, key: &str, locked: bool) -> sqlx::Result<bool> {
+    let query = r#"
+        UPDATE `regexps` SET `locked` = ? WHERE `key` = ?
+    "#;
+
+    let result = sqlx::query(query)
+        .bind(locked)
+        .bind(key)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn is_locked(pool: &MySqlPool, key: &str) -> sqlx::Result<bool> {
+    let query = r#"
+        SELECT COUNT(*) AS `count` FROM `regexps` WHERE `key` = ? AND `locked`
+    "#;
+
+    let row: Count = sqlx::query_as(query).bind(key).fetch_one(pool).await?;
+
+    Ok(row.count > 0)
+}
+
+/// ユーザーが正規表現を保存したり書き換えたりできないようにする
+/// すでにされていた場合は `false` を返す
+pub async fn ban(
+    pool: &MySqlPool,
+    user_id: &str,
+    user_name: &str,
+    reason: Option<&str>,
+    banned_by: &str,
+) -> sqlx::Result<bool> {
+    let query = r#"
+        INSERT IGNORE INTO `banned_users` (`user_id`, `user_name`, `reason`, `banned_by`)
+        VALUES (?, ?, ?, ?)
+    "#;
+
+    let result = sqlx::query(query)
+        .bind(user_id)
+        .bind(user_name)
+        .bind(reason)
+        .bind(banned_by)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn unban(pool: &MySqlPool, user_id: &str) -> sqlx::Result<bool> {
+    let query = r#"
+        DELETE FROM `banned_users` WHERE `user_id` = ?
+    "#;
+
+    let result = sqlx::query(query).bind(user_id).execute(pool).await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn is_banned(pool: &MySqlPool, user_id: &str) -> sqlx::Result<bool> {
+    let query = r#"
+        SELECT COUNT(*) AS `count` FROM `banned_users` WHERE `user_id` = ?
+    "#;
+
+    let row: Count = sqlx::query_as(query).bind(user_id).fetch_one(pool).await?;
+
+    Ok(row.count > 0)
+}
+
+/// 管理者が行った操作を記録する
+pub async fn add_audit_log(
+    pool: &MySqlPool,
+    user_id: &str,
+    user_name: &str,
+    action: &str,
+    target: &str,
+    detail: Option<&str>,
+) -> sqlx::Result<()> {
+    let query = r#"
+        INSERT INTO `audit_logs` (`user_id`, `user_name`, `action`, `target`, `detail`)
+        VALUES (?, ?, ?, ?, ?)
+    "#;
+
+    sqlx::query(query)
+        .bind(user_id)
+        .bind(user_name)
+        .bind(action)
+        .bind(target)
+        .bind(detail)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct AuditLog {
+    pub user_name: String,
+    pub action: String,
+    pub target: String,
+    pub detail: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// 新しいものから順に、最大 `limit` 件の監査ログを返す
+pub async fn audit_logs(pool: &MySqlPool, limit: i64) -> sqlx::Result<Vec<AuditLog>> {
+    let query = r#"
+        SELECT `user_name`, `action`, `target`, `detail`, `created_at`
+        FROM `audit_logs` ORDER BY `id` DESC LIMIT ?
+    "#;
+
+    sqlx::query_as(query).bind(limit).fetch_all(pool).await
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct Alias {
+    pub key: String,
+    pub user_id: String,
+}
+
+pub async fn get_alias(pool: &MySqlPool, alias: &str) -> sqlx::Result<Option<Alias>> {
+    let query = r#"
+        SELECT `key`, `user_id` FROM `regexp_aliases` WHERE `alias` = ?
+    "#;
+
+    sqlx::query_as(query).bind(alias).fetch_optional(pool).await
+}
+
+/// key に別名をつける
+/// 別名がすでに使われていた場合は `false` を返す
+pub async fn add_alias(
+    pool: &MySqlPool,
+    alias: &str,
+    key: &str,
+    user_id: &str,
+    user_name: &str,
+) -> sqlx::Result<bool> {
+    let query = r#"
+        INSERT IGNORE INTO `regexp_aliases` (`alias`, `key`, `user_id`, `user_name`)
+        VALUES (?, ?, ?, ?)
+    "#;
+
+    let result = sqlx::query(query)
+        .bind(alias)
+        .bind(key)
+        .bind(user_id)
+        .bind(user_name)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn remove_alias(pool: &MySqlPool, alias: &str) -> sqlx::Result<bool> {
+    let query = r#"
+        DELETE FROM `regexp_aliases` WHERE `alias` = ?
+    "#;
+
+    let result = sqlx::query(query).bind(alias).execute(pool).await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// key につけられた別名を返す
+pub async fn aliases(pool: &MySqlPool, key: &str) -> sqlx::Result<Vec<String>> {
+    let query = r#"
+        SELECT `alias` FROM `regexp_aliases` WHERE `key` = ? ORDER BY `created_at`
+    "#;
+
+    let rows: Vec<(String,)> = sqlx::query_as(query).bind(key).fetch_all(pool).await?;
+
+    Ok(rows.into_iter().map(|(alias,)| alias).collect())
+}
+
+/// key をお気に入りに追加する
+/// すでに追加されていた場合は `false` を返す
+pub async fn add_favorite(pool: &MySqlPool, user_id: &str, key: &str) -> sqlx::Result<bool> {
+    let query = r#"
+        INSERT IGNORE INTO `regexp_favorites` (`user_id`, `key`) VALUES (?, ?)
+    "#;
+
+    let result = sqlx::query(query)
+        .bind(user_id)
+        .bind(key)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn remove_favorite(pool: &MySqlPool, user_id: &str, key: &str) -> sqlx::Result<bool> {
+    let query = r#"
+        DELETE FROM `regexp_favorites` WHERE `user_id` = ? AND `key` = ?
+    "#;
+
+    let result = sqlx::query(query)
+        .bind(user_id)
+        .bind(key)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// 書き出すときに読む行
+#[derive(sqlx::FromRow, Debug)]
+struct ExportRow {
+    key: String,
+    regexp: String,
+    description: Option<String>,
+    tags: Option<String>,
+    user_id: String,
+    user_name: String,
+}
+
+/// `filter` に一致するものを、説明とタグを含めて key の順に返す
+pub async fn export(pool: &MySqlPool, filter: &ListFilter) -> sqlx::Result<Vec<dump::Pattern>> {
+    let mut builder = QueryBuilder::new(
+        r#"
+        SELECT `key`, `regexp`, `description`, `user_id`, `user_name`,
+            (SELECT GROUP_CONCAT(`tag` ORDER BY `tag` SEPARATOR ',') FROM `regexp_tags`
+            WHERE `regexp_tags`.`key` = `regexps`.`key`) AS `tags`
+        FROM `regexps`"#,
+    );
+    filter.push_conditions(&mut builder);
+    builder.push(" ORDER BY `key`");
+    let rows: Vec<ExportRow> = builder.build_query_as().fetch_all(pool).await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| dump::Pattern {
+            key: row.key,
+            regexp: row.regexp,
+            description: row.description,
+            tags: row
+                .tags
+                .map(|tags| tags.split(',').map(str::to_string).collect())
+                .unwrap_or_default(),
+            user_id: Some(row.user_id),
+            user_name: Some(row.user_name),
+        })
+        .collect())
+}
+
+/// 取り込むときに、同じ key がすでにあった場合の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Conflict {
+    /// 取り込まない
+    #[default]
+    Skip,
+    /// 取り込んだもので書き換える
+    Overwrite,
+    /// `key-2` のように番号をつけた key で保存する
+    Rename,
+}
+
+/// `Conflict::Rename` で試す番号の最大値
+const RENAME_LIMIT: usize = 100;
+
+/// 取り込んだ結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Imported {
+    Saved,
+    Overwritten,
+    Renamed(String),
+    Skipped,
+}
+
+/// key の重複によるエラー (code: 1062) か
+pub fn is_duplicate(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .is_some_and(|e| e.code().is_some_and(|code| code == "1062"))
+}
+
+/// 取り込んだ正規表現を `user_id` のものとして保存する
+/// 別名として使われている key も、すでにあるものとして扱う
+pub async fn import(
+    pool: &MySqlPool,
+    pattern: &dump::Pattern,
+    conflict: Conflict,
+    user_id: &str,
+    user_name: &str,
+) -> sqlx::Result<Imported> {
+    let metadata = Metadata {
+        description: pattern.description.clone(),
+        tags: Some(pattern.tags.clone()),
+    };
+    let key = &pattern.key;
+    if try_save(pool, key, &pattern.regexp, &metadata, user_id, user_name).await? {
+        return Ok(Imported::Saved);
+    }
+
+    match conflict {
+        Conflict::Skip => Ok(Imported::Skipped),
+        Conflict::Overwrite => {
+            if get_alias(pool, key).await?.is_some() {
+                return Ok(Imported::Skipped);
+            }
+            let updated = update(pool, key, &pattern.regexp, &metadata, user_id, user_name).await?;
+            Ok(if updated {
+                Imported::Overwritten
+            } else {
+                Imported::Skipped
+            })
+        }
+        Conflict::Rename => {
+            for n in 2..=RENAME_LIMIT {
+                let renamed = format!("{}-{}", key, n);
+                // 番号をつけると長すぎる場合は、それ以上試さない
+                if key::validate(&renamed).is_err() {
+                    break;
+                }
+                if try_save(
+                    pool,
+                    &renamed,
+                    &pattern.regexp,
+                    &metadata,
+                    user_id,
+                    user_name,
+                )
+                .await?
+                {
+                    return Ok(Imported::Renamed(renamed));
+                }
+            }
+            Ok(Imported::Skipped)
+        }
+    }
+}
+
+/// 保存し、key がすでに使われていた場合は `false` を返す
+async fn try_save(
+    pool: &MySqlPool,
+    key: &str,
+    value: &str,
+    metadata: &Metadata,
+    user_id: &str,
+    user_name: &str,
+) -> sqlx::Result<bool> {
+    if get_alias(pool, key).await?.is_some() {
+        return Ok(false);
+    }
+    match save(pool, key, value, metadata, user_id, user_name).await {
+        Ok(()) => Ok(true),
+        Err(e) if is_duplicate(&e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// `MARIADB_*` で指定したデータベースを使うので、`cargo test -- --ignored` で実行する
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// データベースに接続し、他のテストと重ならない key を返す
+    async fn setup() -> (MySqlPool, String) {
+        let pool = connect_db().await.unwrap();
+        let key = format!("test-{:08x}", rand::random::<u32>());
+        (pool, key)
+    }
+
+    /// テストで保存したものを完全に消す
+    async fn cleanup(pool: &MySqlPool, key: &str) {
+        for table in ["regexps", "regexp_revisions", "regexp_tags"] {
+            sqlx::query(&format!("DELETE FROM `{}` WHERE `key` = ?", table))
+                .bind(key)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn actions(pool: &MySqlPool, key: &str) -> Vec<String> {
+        let revisions = history(pool, key, 10).await.unwrap();
+        revisions.into_iter().map(|r| r.action).collect()
+    }
+
+    #[tokio::test]
+    #[ignore = "requires MariaDB"]
+    async fn test_remove() {
+        let (pool, key) = setup().await;
+        let metadata = Metadata::default();
+
+        assert!(!remove(&pool, &key, "id", "name").await.unwrap());
+        save(&pool, &key, "a", &metadata, "id", "name")
+            .await
+            .unwrap();
+        assert!(remove(&pool, &key, "id", "name").await.unwrap());
+        assert!(!remove(&pool, &key, "id", "name").await.unwrap());
+        assert_eq!(get(&pool, &key).await.unwrap(), None);
+        assert_eq!(actions(&pool, &key).await, ["delete", "save"]);
+
+        cleanup(&pool, &key).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires MariaDB"]
+    async fn test_concurrent_remove() {
+        let (pool, key) = setup().await;
+        save(&pool, &key, "a", &Metadata::default(), "id", "name")
+            .await
+            .unwrap();
+
+        // 同時に削除しても、削除したと返すのは 1 つだけ
+        let results = tokio::try_join!(
+            remove(&pool, &key, "id", "name"),
+            remove(&pool, &key, "id", "name"),
+            remove(&pool, &key, "id", "name")
+        )
+        .unwrap();
+        let removed = [results.0, results.1, results.2];
+        assert_eq!(removed.iter().filter(|&&r| r).count(), 1);
+        assert_eq!(actions(&pool, &key).await, ["delete", "save"]);
+
+        cleanup(&pool, &key).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires MariaDB"]
+    async fn test_update() {
+        let (pool, key) = setup().await;
+        let metadata = Metadata {
+            description: Some("説明".to_string()),
+            tags: Some(vec!["tag".to_string()]),
+        };
+
+        assert!(!update(&pool, &key, "b", &metadata, "id", "name")
+            .await
+            .unwrap());
+        assert!(actions(&pool, &key).await.is_empty());
+
+        save(&pool, &key, "a", &Metadata::default(), "id", "name")
+            .await
+            .unwrap();
+        assert!(update(&pool, &key, "b", &metadata, "id", "name")
+            .await
+            .unwrap());
+        assert_eq!(get(&pool, &key).await.unwrap().as_deref(), Some("b"));
+        assert_eq!(tags(&pool, &key).await.unwrap(), ["tag"]);
+        assert_eq!(actions(&pool, &key).await, ["update", "save"]);
+
+        // タグを指定しなければそのまま残り、空にするとすべて外れる
+        assert!(update(&pool, &key, "c", &Metadata::default(), "id", "name")
+            .await
+            .unwrap());
+        assert_eq!(tags(&pool, &key).await.unwrap(), ["tag"]);
+        let cleared = Metadata {
+            description: None,
+            tags: Some(vec![]),
+        };
+        assert!(update(&pool, &key, "d", &cleared, "id", "name")
+            .await
+            .unwrap());
+        assert!(tags(&pool, &key).await.unwrap().is_empty());
+
+        cleanup(&pool, &key).await;
+    }
+
+    /// 削除した日時を `days` 日前にする
+    async fn set_deleted_days_ago(pool: &MySqlPool, key: &str, days: u32) {
+        let query = r#"
+            UPDATE `regexps` SET `deleted_at` = CURRENT_TIMESTAMP - INTERVAL ? DAY WHERE `key` = ?
+        "#;
+        sqlx::query(query)
+            .bind(days)
+            .bind(key)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("a_b%c\\d"), "a\\_b\\%c\\\\d");
+        assert_eq!(escape_like("おみくじ"), "おみくじ");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires MariaDB"]
+    async fn test_search_like() {
+        let (pool, key) = setup().await;
+        let filter = ListFilter {
+            prefix: Some(key.clone()),
+            ..Default::default()
+        };
+        let metadata = Metadata {
+            description: Some("今日の運勢".to_string()),
+            tags: None,
+        };
+        let keys = [format!("{}-omikuji", key), format!("{}-fortune", key)];
+        save(
+            &pool,
+            &keys[0],
+            "(大|小)吉|凶",
+            &Metadata::default(),
+            "id",
+            "name",
+        )
+        .await
+        .unwrap();
+        save(&pool, &keys[1], "吉", &metadata, "id", "name")
+            .await
+            .unwrap();
+
+        // key に一致するものが、正規表現や説明に一致するものより先に並ぶ
+        let words = ["omikuji".to_string(), "吉".to_string()];
+        let entries = search_like(&pool, &filter, &words, 10).await.unwrap();
+        let found = entries.iter().map(|e| e.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(found, [keys[0].as_str(), keys[1].as_str()]);
+
+        let words = ["運勢".to_string()];
+        let entries = search_like(&pool, &filter, &words, 10).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, keys[1]);
+
+        let words = ["100%".to_string()];
+        assert!(search_like(&pool, &filter, &words, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        for key in &keys {
+            cleanup(&pool, key).await;
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires MariaDB"]
+    async fn test_random_entry() {
+        let (pool, key) = setup().await;
+        let filter = ListFilter {
+            prefix: Some(key.clone()),
+            ..Default::default()
+        };
+        assert!(random_entry(&pool, &filter).await.unwrap().is_none());
+
+        let keys = [format!("{}-a", key), format!("{}-b", key)];
+        for key in &keys {
+            save(&pool, key, "a", &Metadata::default(), "id", "name")
+                .await
+                .unwrap();
+        }
+        // 一致するものの範囲から選ぶので、他の key が多くてもどちらも選ばれる
+        let mut seen = HashSet::new();
+        for _ in 0..50 {
+            let entry = random_entry(&pool, &filter).await.unwrap().unwrap();
+            assert!(keys.contains(&entry.key));
+            seen.insert(entry.key);
+        }
+        assert_eq!(seen.len(), keys.len());
+
+        for key in &keys {
+            cleanup(&pool, key).await;
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires MariaDB"]
+    async fn test_purge_tombstones() {
+        let (pool, key) = setup().await;
+        let expired = format!("{}-expired", key);
+        for key in [&key, &expired] {
+            save(&pool, key, "a", &Metadata::default(), "id", "name")
+                .await
+                .unwrap();
+            assert!(remove(&pool, key, "id", "name").await.unwrap());
+        }
+        set_deleted_days_ago(&pool, &key, 29).await;
+        set_deleted_days_ago(&pool, &expired, 31).await;
+
+        // 保存しておく日数が過ぎたものだけが消え、同じ key で保存し直せる
+        assert!(purge_tombstones(&pool, 30).await.unwrap() >= 1);
+        assert!(deleted_at(&pool, &key).await.unwrap().is_some());
+        assert_eq!(deleted_at(&pool, &expired).await.unwrap(), None);
+        assert!(history(&pool, &expired, 10).await.unwrap().is_empty());
+        assert!(!history(&pool, &key, 10).await.unwrap().is_empty());
+        assert!(
+            save(&pool, &expired, "b", &Metadata::default(), "id", "name")
+                .await
+                .is_ok()
+        );
+        assert!(save(&pool, &key, "b", &Metadata::default(), "id", "name")
+            .await
+            .is_err());
+
+        cleanup(&pool, &key).await;
+        cleanup(&pool, &expired).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires MariaDB"]
+    async fn test_purge_keeps_locked() {
+        let (pool, key) = setup().await;
+        save(&pool, &key, "a", &Metadata::default(), "id", "name")
+            .await
+            .unwrap();
+        assert!(remove(&pool, &key, "admin", "admin").await.unwrap());
+        assert!(set_locked(&pool, &key, true).await.unwrap());
+        set_deleted_days_ago(&pool, &key, 31).await;
+
+        purge_tombstones(&pool, 30).await.unwrap();
+        assert!(is_locked(&pool, &key).await.unwrap());
+        assert!(save(&pool, &key, "b", &Metadata::default(), "id", "name")
+            .await
+            .is_err());
+
+        cleanup(&pool, &key).await;
+    }
+}
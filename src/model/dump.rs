@@ -0,0 +1,113 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// 書き出すファイルの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+}
+
+impl Format {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Format::Json => "regexps.json",
+            Format::Yaml => "regexps.yaml",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Yaml => "application/yaml",
+        }
+    }
+}
+
+/// 書き出される正規表現
+/// 作成者は、取り込むときに指定されなかった場合に使う
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pattern {
+    pub key: String,
+    pub regexp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Dump {
+    patterns: Vec<Pattern>,
+}
+
+pub fn to_string(patterns: Vec<Pattern>, format: Format) -> anyhow::Result<String> {
+    let dump = Dump { patterns };
+    Ok(match format {
+        Format::Json => serde_json::to_string_pretty(&dump)?,
+        Format::Yaml => serde_yaml::to_string(&dump)?,
+    })
+}
+
+/// JSON か YAML で書かれた正規表現を読む
+/// `{ "patterns": [...] }` の形と、配列だけの形のどちらも読める
+pub fn parse(text: &str) -> anyhow::Result<Vec<Pattern>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum File {
+        Dump(Dump),
+        Patterns(Vec<Pattern>),
+    }
+
+    // YAML は JSON を含むので、どちらも YAML として読める
+    let file: File = serde_yaml::from_str(text).context("JSON または YAML として読めません")?;
+    Ok(match file {
+        File::Dump(dump) => dump.patterns,
+        File::Patterns(patterns) => patterns,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns() -> Vec<Pattern> {
+        vec![
+            Pattern {
+                key: "omikuji".to_string(),
+                regexp: "(大|小)吉|凶".to_string(),
+                description: Some("今日の運勢".to_string()),
+                tags: vec!["fortune".to_string()],
+                user_id: None,
+                user_name: Some("SSlime".to_string()),
+            },
+            Pattern {
+                key: "traP".to_string(),
+                regexp: "[a-z]{3}[A-Z]".to_string(),
+                description: None,
+                tags: vec![],
+                user_id: None,
+                user_name: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for format in [Format::Json, Format::Yaml] {
+            let text = to_string(patterns(), format).unwrap();
+            assert_eq!(parse(&text).unwrap(), patterns());
+        }
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let text = r#"[{"key": "traP", "regexp": "[a-z]{3}[A-Z]"}]"#;
+        assert_eq!(parse(text).unwrap(), patterns()[1..]);
+        assert!(parse(r#"[{"key": "traP"}]"#).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+/// 保存するときの key の最大の長さ
+/// `regexps`.`key` などの列は VARCHAR(80)
+pub const KEY_LENGTH_LIMIT: usize = 80;
+
+/// 記号のうち、key に使えるもの
+const ALLOWED_SYMBOLS: &[char] = &['-', '_', '.', '/', '+', ':', '!', '?'];
+
+/// key として使えない名前
+/// コマンドと紛らわしいので、コマンド名と `~me` の `me` を使えないようにする
+pub const RESERVED_NAMES: &[&str] = &[
+    "regex",
+    "regexp",
+    "rand",
+    "random",
+    "randregex",
+    "randregexp",
+    "explain",
+    "describe",
+    "prob",
+    "probability",
+    "stats",
+    "statistics",
+    "match",
+    "test",
+    "save",
+    "memory",
+    "update",
+    "overwrite",
+    "call",
+    "load",
+    "lucky",
+    "fav",
+    "favorite",
+    "unfav",
+    "unfavorite",
+    "favs",
+    "favorites",
+    "info",
+    "show",
+    "remove",
+    "delete",
+    "forget",
+    "restore",
+    "undelete",
+    "transfer",
+    "give",
+    "maintainer",
+    "maintainers",
+    "alias",
+    "unalias",
+    "remove-alias",
+    "history",
+    "log",
+    "rollback",
+    "revert",
+    "list",
+    "ls",
+    "ranking",
+    "rank",
+    "mystats",
+    "me",
+    "search",
+    "find",
+    "export",
+    "import",
+    "admin",
+    "join",
+    "leave",
+    "bye",
+    "help",
+    "usage",
+    "readme",
+];
+
+/// `#{UUID}/` や `~{UUID}/` を除いた、key の名前の部分を返す
+fn name(stored: &str) -> &str {
+    &stored[scope(stored).len()..]
+}
+
+/// key の範囲を表す `#{UUID}/` や `~{UUID}/` を返す
+/// 全体の key の場合は空文字列を返す
+fn scope(stored: &str) -> &str {
+    match stored.strip_prefix(['#', '~']) {
+        Some(rest) => rest.find('/').map_or(stored, |i| &stored[..i + 2]),
+        None => "",
+    }
+}
+
+pub fn is_uuid(s: &str) -> bool {
+    s.len() == 36 && s.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+}
+
+/// 保存するときの形の key の範囲が、`#{UUID}/` や `~{UUID}/` になっているか
+/// `~me/` や `#gps/times/` のような、解決する前の形の key は false になる
+pub fn is_resolved(stored: &str) -> bool {
+    let scope = scope(stored);
+    scope.is_empty() || (scope.ends_with('/') && is_uuid(&scope[1..scope.len() - 1]))
+}
+
+/// ユーザーの key の場合は、そのユーザーの UUID を返す
+pub fn user_id(stored: &str) -> Option<&str> {
+    let scope = scope(stored);
+    (scope.starts_with('~') && scope.ends_with('/')).then(|| &scope[1..scope.len() - 1])
+}
+
+/// 2 つの保存するときの形の key が、同じ範囲のものか
+/// 別名は、別名をつけられた key と同じ範囲にしか作れない
+pub fn same_scope(a: &str, b: &str) -> bool {
+    scope(a) == scope(b)
+}
+
+/// 保存するときの形の key が、新しく保存できるものかを確かめる
+/// 使えない場合は、返信する文章を返す
+pub fn validate(stored: &str) -> Result<(), String> {
+    let name = name(stored);
+    if name.is_empty() {
+        return Err("key の名前が空です".to_string());
+    }
+
+    let length = stored.chars().count();
+    if length > KEY_LENGTH_LIMIT {
+        // チャンネルやユーザーの key は、接頭辞の分だけ短くなる
+        let limit = KEY_LENGTH_LIMIT - (length - name.chars().count());
+        return Err(format!(
+            "key の名前は {} 文字までです ({} 文字)",
+            limit,
+            name.chars().count()
+        ));
+    }
+
+    let mut invalid = name
+        .chars()
+        .filter(|c| !(c.is_alphanumeric() || ALLOWED_SYMBOLS.contains(c)))
+        .collect::<Vec<_>>();
+    if !invalid.is_empty() {
+        invalid.dedup();
+        let invalid = invalid
+            .iter()
+            .map(|c| format!("{:?}", c))
+            .collect::<Vec<_>>();
+        return Err(format!(
+            "key に使えない文字が含まれています: {}\n使えるのは文字・数字と {} です",
+            invalid.join(", "),
+            ALLOWED_SYMBOLS.iter().collect::<String>()
+        ));
+    }
+
+    if name.starts_with('-') {
+        return Err("key の名前を - で始めることはできません".to_string());
+    }
+    if RESERVED_NAMES.contains(&name.to_lowercase().as_str()) {
+        return Err(format!(
+            "\"{}\" はコマンド名と紛らわしいので、key の名前には使えません",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "0a0be82e-a9a1-4211-89c6-f7dbb0dced8c";
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("traP").is_ok());
+        assert!(validate("おみくじ-2").is_ok());
+        assert!(validate(&format!("~{USER}/gps/times")).is_ok());
+        assert!(validate(&"a".repeat(KEY_LENGTH_LIMIT)).is_ok());
+
+        assert!(validate("").is_err());
+        assert!(validate(&format!("~{USER}/")).is_err());
+        assert!(validate("a`b").is_err());
+        assert!(validate("a\"b").is_err());
+        assert!(validate("--force").is_err());
+        assert!(validate("List").is_err());
+        assert!(validate(&format!("#{USER}/help")).is_err());
+        assert!(validate(&"a".repeat(KEY_LENGTH_LIMIT + 1)).is_err());
+    }
+
+    #[test]
+    fn test_same_scope() {
+        let private = format!("~{USER}/omikuji");
+        let channel = format!("#{USER}/omikuji");
+        assert!(same_scope("omikuji", "traP"));
+        assert!(same_scope(&private, &format!("~{USER}/o")));
+        assert!(!same_scope("o", &private));
+        assert!(!same_scope("o", &channel));
+        assert!(!same_scope(&format!("#{USER}/o"), &private));
+        assert!(!same_scope(&format!("~{}/o", "1".repeat(36)), &private));
+    }
+
+    #[test]
+    fn test_is_resolved() {
+        assert!(is_resolved("omikuji"));
+        assert!(is_resolved(&format!("~{USER}/omikuji")));
+        assert!(is_resolved(&format!("#{USER}/gps/times")));
+        assert!(!is_resolved("~me/omikuji"));
+        assert!(!is_resolved("~/omikuji"));
+        assert!(!is_resolved("#/omikuji"));
+        assert!(!is_resolved("#gps/times/omikuji"));
+        assert!(!is_resolved("~omikuji"));
+
+        assert_eq!(user_id(&format!("~{USER}/omikuji")), Some(USER));
+        assert_eq!(user_id(&format!("#{USER}/omikuji")), None);
+        assert_eq!(user_id("omikuji"), None);
+    }
+
+    #[test]
+    fn test_length_message() {
+        let name = "a".repeat(50);
+        assert_eq!(
+            validate(&format!("~{USER}/{name}")),
+            Err("key の名前は 42 文字までです (50 文字)".to_string())
+        );
+    }
+}
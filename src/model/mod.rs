@@ -1,5 +1,10 @@
 pub mod api;
 pub mod db;
+pub mod dump;
+pub mod key;
+pub mod store;
+
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Literal {
@@ -51,3 +56,97 @@ pub enum Terms {
 pub enum Expression {
     Union(Vec<Terms>),
 }
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Char(c) => write!(f, "{}", c),
+            Literal::Escape(c) => write!(f, "\\{}", c),
+        }
+    }
+}
+
+impl fmt::Display for ClassElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClassElement::Range(a, b) => write!(f, "{}-{}", a, b),
+            ClassElement::Literal(l) => write!(f, "{}", l),
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Literal(l) => write!(f, "{}", l),
+            Token::Class(elements) => {
+                write!(f, "[")?;
+                for e in elements {
+                    write!(f, "{}", e)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Factor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Factor::Token(t) => write!(f, "{}", t),
+            Factor::Group(e) => write!(f, "({})", e),
+            Factor::FixedGroup(e) => write!(f, "<{}>", e),
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Factor(factor) => write!(f, "{}", factor),
+            Term::WithSuffix(factor, suffix) => write!(f, "{}{}", factor, suffix),
+        }
+    }
+}
+
+impl fmt::Display for TermSuffix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TermSuffix::Question => write!(f, "?"),
+            TermSuffix::Asterisk => write!(f, "*"),
+            TermSuffix::Plus => write!(f, "+"),
+            TermSuffix::Range(a, b) => write!(f, "{{{},{}}}", a, b),
+            TermSuffix::OpenRange(a) => write!(f, "{{{},}}", a),
+            TermSuffix::Repeat(n) => write!(f, "{{{}}}", n),
+        }
+    }
+}
+
+impl fmt::Display for Terms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terms::Concat(terms) => {
+                for t in terms {
+                    write!(f, "{}", t)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Union(terms) => {
+                for (i, t) in terms.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "|")?;
+                    }
+                    write!(f, "{}", t)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use async_trait::async_trait;
+use sqlx::MySqlPool;
+
+use crate::model::db::{self, ListFilter, Metadata, Page};
+
+use super::{PatternStore, Role};
+
+/// 履歴などもあわせて記録する、MariaDB への保存
+pub struct MariaDbStore {
+    pool: MySqlPool,
+}
+
+impl MariaDbStore {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PatternStore for MariaDbStore {
+    async fn save(
+        &self,
+        key: &str,
+        value: &str,
+        metadata: &Metadata,
+        user_id: &str,
+        user_name: &str,
+    ) -> sqlx::Result<bool> {
+        match db::save(&self.pool, key, value, metadata, user_id, user_name).await {
+            Ok(()) => Ok(true),
+            Err(e) if db::is_duplicate(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get(&self, key: &str) -> sqlx::Result<Option<String>> {
+        db::get(&self.pool, key).await
+    }
+
+    /// 別名が見つかった場合は、別名をつけられた key を返す
+    async fn find(&self, candidates: &[String]) -> sqlx::Result<Option<(String, String)>> {
+        let Some(found) = db::find_key(&self.pool, candidates).await? else {
+            return Ok(None);
+        };
+        Ok(self.get(&found).await?.map(|value| (found, value)))
+    }
+
+    async fn owner(&self, key: &str) -> sqlx::Result<Option<String>> {
+        db::get_owner(&self.pool, key).await
+    }
+
+    async fn role(&self, key: &str, user_id: &str) -> sqlx::Result<Option<Role>> {
+        let Some(owner_id) = db::get_owner(&self.pool, key).await? else {
+            return Ok(None);
+        };
+        let role = if owner_id == user_id {
+            Role::Owner
+        } else if db::is_maintainer(&self.pool, key, user_id).await? {
+            Role::Maintainer
+        } else {
+            Role::Other
+        };
+        Ok(Some(role))
+    }
+
+    async fn is_locked(&self, key: &str) -> sqlx::Result<bool> {
+        db::is_locked(&self.pool, key).await
+    }
+
+    async fn remove(&self, key: &str, user_id: &str, user_name: &str) -> sqlx::Result<bool> {
+        db::remove(&self.pool, key, user_id, user_name).await
+    }
+
+    async fn list(&self, filter: &ListFilter, limit: i64, offset: i64) -> sqlx::Result<Page> {
+        db::list(&self.pool, filter, limit, offset).await
+    }
+}
@@ -0,0 +1,151 @@
+use std::{collections::BTreeMap, sync::RwLock};
+
+use async_trait::async_trait;
+
+use crate::model::db::{Entry, ListFilter, Metadata, Page};
+
+use super::PatternStore;
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    regexp: String,
+    tags: Vec<String>,
+    user_id: String,
+    user_name: String,
+}
+
+/// メモリ上への保存
+/// 再起動すると消えるので、動作確認やテストに使う
+/// 履歴やお気に入りは記録せず、削除するとすぐに消える
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    patterns: RwLock<BTreeMap<String, Pattern>>,
+}
+
+/// `key` が `filter` に一致するか
+fn matches(filter: &ListFilter, key: &str, pattern: &Pattern) -> bool {
+    let visible = !(key.starts_with('#') || key.starts_with('~'))
+        || filter
+            .visible_prefixes
+            .iter()
+            .any(|prefix| key.starts_with(prefix.as_str()));
+    visible
+        && filter.user_id.as_ref().is_none_or(|id| *id == pattern.user_id)
+        && filter
+            .user_name
+            .as_ref()
+            .is_none_or(|name| *name == pattern.user_name)
+        && filter
+            .prefix
+            .as_ref()
+            .is_none_or(|prefix| key.starts_with(prefix.as_str()))
+        && filter.tag.as_ref().is_none_or(|tag| pattern.tags.contains(tag))
+        // お気に入りは記録していないので、何も一致しない
+        && filter.favorite_of.is_none()
+}
+
+#[async_trait]
+impl PatternStore for MemoryStore {
+    async fn save(
+        &self,
+        key: &str,
+        value: &str,
+        metadata: &Metadata,
+        user_id: &str,
+        user_name: &str,
+    ) -> sqlx::Result<bool> {
+        let mut patterns = self.patterns.write().unwrap();
+        if patterns.contains_key(key) {
+            return Ok(false);
+        }
+        patterns.insert(
+            key.to_string(),
+            Pattern {
+                regexp: value.to_string(),
+                tags: metadata.tags.clone().unwrap_or_default(),
+                user_id: user_id.to_string(),
+                user_name: user_name.to_string(),
+            },
+        );
+        Ok(true)
+    }
+
+    async fn get(&self, key: &str) -> sqlx::Result<Option<String>> {
+        let patterns = self.patterns.read().unwrap();
+        Ok(patterns.get(key).map(|pattern| pattern.regexp.clone()))
+    }
+
+    async fn owner(&self, key: &str) -> sqlx::Result<Option<String>> {
+        let patterns = self.patterns.read().unwrap();
+        Ok(patterns.get(key).map(|pattern| pattern.user_id.clone()))
+    }
+
+    async fn remove(&self, key: &str, _user_id: &str, _user_name: &str) -> sqlx::Result<bool> {
+        let mut patterns = self.patterns.write().unwrap();
+        Ok(patterns.remove(key).is_some())
+    }
+
+    async fn list(&self, filter: &ListFilter, limit: i64, offset: i64) -> sqlx::Result<Page> {
+        let patterns = self.patterns.read().unwrap();
+        let matched = patterns
+            .iter()
+            .filter(|(key, pattern)| matches(filter, key, pattern))
+            .collect::<Vec<_>>();
+        let entries = matched
+            .iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .map(|(key, pattern)| Entry {
+                key: key.to_string(),
+                regexp: pattern.regexp.clone(),
+                user_name: pattern.user_name.clone(),
+            })
+            .collect();
+        Ok(Page {
+            entries,
+            total: matched.len() as i64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = MemoryStore::default();
+        for key in ["b", "a", "~SSlime/c", "~other/d"] {
+            assert!(store
+                .save(key, key, &Metadata::default(), "id", "SSlime")
+                .await
+                .unwrap());
+        }
+        assert!(!store
+            .save("a", "x", &Metadata::default(), "id", "SSlime")
+            .await
+            .unwrap());
+
+        let candidates = vec!["~SSlime/a".to_string(), "a".to_string()];
+        assert_eq!(
+            store.find(&candidates).await.unwrap(),
+            Some(("a".to_string(), "a".to_string()))
+        );
+
+        let filter = ListFilter {
+            visible_prefixes: vec!["~SSlime/".to_string()],
+            ..Default::default()
+        };
+        let page = store.list(&filter, 2, 1).await.unwrap();
+        assert_eq!(page.total, 3);
+        let keys = page
+            .entries
+            .iter()
+            .map(|e| e.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["b", "~SSlime/c"]);
+
+        assert!(store.remove("a", "id", "SSlime").await.unwrap());
+        assert!(!store.remove("a", "id", "SSlime").await.unwrap());
+    }
+}
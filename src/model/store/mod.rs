@@ -0,0 +1,128 @@
+mod mariadb;
+mod memory;
+mod sqlite;
+
+use std::str::FromStr;
+
+use async_trait::async_trait;
+
+use crate::config::{Resource, SQLITE_URL};
+
+use super::db::{self, ListFilter, Metadata, Page};
+
+pub use self::{mariadb::MariaDbStore, memory::MemoryStore, sqlite::SqliteStore};
+
+/// key に対する権限
+///
+/// 保存した人 (`Owner`) はすべての操作ができ、
+/// 共同管理者 (`Maintainer`) は書き換え・削除・ロールバックができる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Other,
+    Maintainer,
+    Owner,
+}
+
+/// 正規表現を保存する場所
+///
+/// 保存・呼び出し・削除・一覧と、それらに必要な権限の確認だけを扱い、
+/// 履歴や別名、共同管理者の追加などは MariaDB を使っている場合だけ `db` から直接扱う
+#[async_trait]
+pub trait PatternStore: Send + Sync {
+    /// key がすでに使われていた場合は `false` を返す
+    async fn save(
+        &self,
+        key: &str,
+        value: &str,
+        metadata: &Metadata,
+        user_id: &str,
+        user_name: &str,
+    ) -> sqlx::Result<bool>;
+
+    async fn get(&self, key: &str) -> sqlx::Result<Option<String>>;
+
+    /// `candidates` のうち最初に見つかった key と、その正規表現を返す
+    async fn find(&self, candidates: &[String]) -> sqlx::Result<Option<(String, String)>> {
+        for key in candidates {
+            if let Some(value) = self.get(key).await? {
+                return Ok(Some((key.clone(), value)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// key を保存した人の ID を返す
+    async fn owner(&self, key: &str) -> sqlx::Result<Option<String>>;
+
+    /// `user_id` の key に対する権限を返す
+    /// key が見つからない場合は `None` を返す
+    ///
+    /// 共同管理者を記録しない保存先では、保存した人かどうかだけで決まる
+    async fn role(&self, key: &str, user_id: &str) -> sqlx::Result<Option<Role>> {
+        Ok(self.owner(key).await?.map(|owner_id| {
+            if owner_id == user_id {
+                Role::Owner
+            } else {
+                Role::Other
+            }
+        }))
+    }
+
+    /// 管理者にロックされているか
+    /// ロックを記録しない保存先では、常に `false` を返す
+    async fn is_locked(&self, _key: &str) -> sqlx::Result<bool> {
+        Ok(false)
+    }
+
+    /// 削除した場合は `true` を、key が見つからなかった場合は `false` を返す
+    ///
+    /// MariaDB では削除したことを記録して `/restore` で戻せるようにするが、
+    /// それ以外の保存先ではすぐに完全に消す
+    async fn remove(&self, key: &str, user_id: &str, user_name: &str) -> sqlx::Result<bool>;
+
+    async fn list(&self, filter: &ListFilter, limit: i64, offset: i64) -> sqlx::Result<Page>;
+}
+
+/// 正規表現を保存する場所の種類
+/// 環境変数 `STORE_BACKEND` で選ぶ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    MariaDb,
+    Sqlite,
+    /// 再起動すると消える。動作確認やテストに使う
+    Memory,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mariadb" | "mysql" => Ok(Backend::MariaDb),
+            "sqlite" => Ok(Backend::Sqlite),
+            "memory" => Ok(Backend::Memory),
+            _ => Err(format!("Unknown backend: {}", s)),
+        }
+    }
+}
+
+/// `backend` に接続する
+pub async fn connect(backend: Backend) -> anyhow::Result<Resource> {
+    Ok(match backend {
+        Backend::MariaDb => {
+            let pool = db::connect_db().await?;
+            Resource {
+                store: Box::new(MariaDbStore::new(pool.clone())),
+                pool: Some(pool),
+            }
+        }
+        Backend::Sqlite => Resource {
+            store: Box::new(SqliteStore::connect(&SQLITE_URL).await?),
+            pool: None,
+        },
+        Backend::Memory => Resource {
+            store: Box::new(MemoryStore::default()),
+            pool: None,
+        },
+    })
+}
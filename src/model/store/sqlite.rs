@@ -0,0 +1,207 @@
+use async_trait::async_trait;
+use sqlx::{sqlite::SqlitePoolOptions, QueryBuilder, Sqlite, SqlitePool};
+
+use crate::model::db::{Count, ListFilter, Metadata, Page, Regexp};
+
+use super::PatternStore;
+
+/// 小さな環境や手元での開発向けの、SQLite への保存
+/// 履歴は残さず、削除するとすぐに消える
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// `url` に接続し、`migrations/sqlite/` のうちまだ適用していないものを適用する
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(url)
+            .await?;
+
+        sqlx::migrate!("./migrations/sqlite").run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+}
+
+/// `filter` の条件を `WHERE` 以降に追加する
+///
+/// `instr(a, b) = 1` で、a が b で始まることを表す
+fn push_conditions<'a>(filter: &'a ListFilter, builder: &mut QueryBuilder<'a, Sqlite>) {
+    builder.push(" WHERE (instr(`key`, '#') != 1 AND instr(`key`, '~') != 1");
+    for prefix in &filter.visible_prefixes {
+        builder
+            .push(" OR instr(`key`, ")
+            .push_bind(prefix)
+            .push(") = 1");
+    }
+    builder.push(")");
+    if let Some(user_id) = &filter.user_id {
+        builder.push(" AND `user_id` = ").push_bind(user_id);
+    }
+    if let Some(user_name) = &filter.user_name {
+        builder.push(" AND `user_name` = ").push_bind(user_name);
+    }
+    if let Some(prefix) = &filter.prefix {
+        builder
+            .push(" AND instr(`key`, ")
+            .push_bind(prefix)
+            .push(") = 1");
+    }
+    if let Some(tag) = &filter.tag {
+        builder
+            .push(" AND instr(`tags`, ',' || ")
+            .push_bind(tag)
+            .push(" || ',') > 0");
+    }
+    // お気に入りは記録していないので、何も一致しない
+    if filter.favorite_of.is_some() {
+        builder.push(" AND 0");
+    }
+}
+
+/// タグを `tags` 列に保存する形にする
+fn tags_column(tags: &[String]) -> String {
+    tags.iter().fold(",".to_string(), |s, tag| s + tag + ",")
+}
+
+#[async_trait]
+impl PatternStore for SqliteStore {
+    async fn save(
+        &self,
+        key: &str,
+        value: &str,
+        metadata: &Metadata,
+        user_id: &str,
+        user_name: &str,
+    ) -> sqlx::Result<bool> {
+        let query = r#"
+            INSERT INTO `regexps` (`key`, `regexp`, `description`, `tags`, `user_id`, `user_name`)
+            VALUES (?, ?, ?, ?, ?, ?)
+        "#;
+
+        let result = sqlx::query(query)
+            .bind(key)
+            .bind(value)
+            .bind(&metadata.description)
+            .bind(tags_column(metadata.tags.as_deref().unwrap_or_default()))
+            .bind(user_id)
+            .bind(user_name)
+            .execute(&self.pool)
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get(&self, key: &str) -> sqlx::Result<Option<String>> {
+        let query = r#"
+            SELECT `regexp` FROM `regexps` WHERE `key` = ?
+        "#;
+
+        let row: Option<Regexp> = sqlx::query_as(query)
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| row.regexp))
+    }
+
+    async fn owner(&self, key: &str) -> sqlx::Result<Option<String>> {
+        let query = r#"
+            SELECT `user_id` FROM `regexps` WHERE `key` = ?
+        "#;
+
+        sqlx::query_scalar(query)
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn remove(&self, key: &str, _user_id: &str, _user_name: &str) -> sqlx::Result<bool> {
+        let query = r#"
+            DELETE FROM `regexps` WHERE `key` = ?
+        "#;
+
+        let result = sqlx::query(query).bind(key).execute(&self.pool).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list(&self, filter: &ListFilter, limit: i64, offset: i64) -> sqlx::Result<Page> {
+        let mut builder = QueryBuilder::new("SELECT COUNT(*) AS `count` FROM `regexps`");
+        push_conditions(filter, &mut builder);
+        let count: Count = builder.build_query_as().fetch_one(&self.pool).await?;
+
+        let mut builder = QueryBuilder::new("SELECT `key`, `regexp`, `user_name` FROM `regexps`");
+        push_conditions(filter, &mut builder);
+        builder
+            .push(" ORDER BY `key` LIMIT ")
+            .push_bind(limit)
+            .push(" OFFSET ")
+            .push_bind(offset);
+        let entries = builder.build_query_as().fetch_all(&self.pool).await?;
+
+        Ok(Page {
+            entries,
+            total: count.count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        let store = SqliteStore::connect("sqlite::memory:").await.unwrap();
+        let metadata = Metadata {
+            description: None,
+            tags: Some(vec!["fortune".to_string()]),
+        };
+        assert!(store
+            .save("omikuji", "(大|小)吉|凶", &metadata, "id", "SSlime")
+            .await
+            .unwrap());
+        assert!(!store
+            .save("omikuji", "吉", &Metadata::default(), "id", "SSlime")
+            .await
+            .unwrap());
+        store
+            .save(
+                "#gps/times/SSlime/a",
+                "a",
+                &Metadata::default(),
+                "id",
+                "SSlime",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get("omikuji").await.unwrap().as_deref(),
+            Some("(大|小)吉|凶")
+        );
+        assert_eq!(store.owner("omikuji").await.unwrap().as_deref(), Some("id"));
+
+        let page = store.list(&ListFilter::default(), 10, 0).await.unwrap();
+        assert_eq!(page.total, 1);
+        let filter = ListFilter {
+            tag: Some("fortune".to_string()),
+            visible_prefixes: vec!["#gps/times/SSlime/".to_string()],
+            ..Default::default()
+        };
+        let page = store.list(&filter, 10, 0).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0].key, "omikuji");
+
+        assert!(store.remove("omikuji", "id", "SSlime").await.unwrap());
+        assert!(!store.remove("omikuji", "id", "SSlime").await.unwrap());
+        assert_eq!(store.get("omikuji").await.unwrap(), None);
+    }
+}
@@ -1,9 +1,14 @@
+use std::fmt;
+
 use nom::{multi::many1, sequence::delimited, IResult};
 
-use crate::model::{ClassElement, Expression, Factor, Literal, Term, TermSuffix, Terms, Token};
+use crate::{
+    config::PARSER_LIMITS,
+    model::{ClassElement, Expression, Factor, Literal, Term, TermSuffix, Terms, Token},
+};
 
-fn literal(s: &str) -> IResult<&str, Literal> {
-    let (s, first) = nom::character::complete::anychar(s)?;
+fn literal(input: &str) -> IResult<&str, Literal> {
+    let (s, first) = nom::character::complete::anychar(input)?;
     if first == '\\' {
         let (s, second) = nom::character::complete::anychar(s)?;
         return Ok((s, Literal::Escape(second)));
@@ -15,7 +20,7 @@ fn literal(s: &str) -> IResult<&str, Literal> {
         '[' | ']' | '(' | ')' | '<' | '>' | '{' | '}' | '?' | '*' | '+' | '|'
     ) {
         return Err(nom::Err::Error(nom::error::Error::new(
-            s,
+            input,
             nom::error::ErrorKind::Tag,
         )));
     }
@@ -158,16 +163,215 @@ fn expression(s: &str) -> IResult<&str, Expression> {
     Ok((s, Expression::Union(contents)))
 }
 
-pub fn parse(s: &str) -> Result<Expression, nom::Err<nom::error::Error<&str>>> {
-    let (s, expression) = expression(s)?;
-    if s.is_empty() {
-        Ok(expression)
-    } else {
-        Err(nom::Err::Error(nom::error::Error::new(
-            s,
-            nom::error::ErrorKind::Eof,
-        )))
+/// 悪意のあるパターンで BOT が止まらないようにするための制限
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// パターンの最大の文字数
+    pub pattern_length: usize,
+    /// `()` と `<>` の入れ子の最大の深さ
+    pub depth: usize,
+    /// 構文木の最大のノード数
+    pub nodes: usize,
+    /// `{}` で指定できる最大の繰り返し回数
+    pub repeat: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            pattern_length: 1000,
+            depth: 32,
+            nodes: 2000,
+            repeat: 10000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// `position` 文字目以降が解釈できなかった
+    Syntax {
+        position: usize,
+    },
+    TooLong {
+        length: usize,
+        limit: usize,
+    },
+    TooDeep {
+        depth: usize,
+        limit: usize,
+    },
+    TooManyNodes {
+        count: usize,
+        limit: usize,
+    },
+    RepeatTooLarge {
+        count: usize,
+        limit: usize,
+    },
+    InvalidRepeatRange {
+        min: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Syntax { position } => {
+                write!(f, "invalid syntax at character {}", position + 1)
+            }
+            ParseError::TooLong { length, limit } => {
+                write!(f, "pattern is too long: {} > {} characters", length, limit)
+            }
+            ParseError::TooDeep { depth, limit } => write!(
+                f,
+                "groups are nested too deeply: {} > {} levels",
+                depth, limit
+            ),
+            ParseError::TooManyNodes { count, limit } => {
+                write!(f, "pattern is too complex: {} > {} nodes", count, limit)
+            }
+            ParseError::RepeatTooLarge { count, limit } => {
+                write!(f, "repeat count is too large: {} > {}", count, limit)
+            }
+            ParseError::InvalidRepeatRange { min, max } => write!(
+                f,
+                "invalid repeat range {{{},{}}}: {} is greater than {}",
+                min, max, min, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// `()` と `<>` の入れ子の深さ
+///
+/// 再帰下降で解析する前に調べることで、深すぎる入れ子でスタックを使い切らないようにする
+fn nesting_depth(s: &str) -> usize {
+    let mut depth = 0usize;
+    let mut max_depth = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '(' | '<' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            ')' | '>' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
     }
+    max_depth
+}
+
+fn node_count(expression: &Expression) -> usize {
+    fn factor(f: &Factor) -> usize {
+        match f {
+            Factor::Token(Token::Literal(_)) => 1,
+            Factor::Token(Token::Class(elements)) => 1 + elements.len(),
+            Factor::Group(e) | Factor::FixedGroup(e) => 1 + node_count(e),
+        }
+    }
+
+    match expression {
+        Expression::Union(terms) => {
+            1 + terms
+                .iter()
+                .map(|Terms::Concat(t)| {
+                    1 + t
+                        .iter()
+                        .map(|t| match t {
+                            Term::Factor(f) => 1 + factor(f),
+                            Term::WithSuffix(f, _) => 2 + factor(f),
+                        })
+                        .sum::<usize>()
+                })
+                .sum::<usize>()
+        }
+    }
+}
+
+fn check_repeats(expression: &Expression, limit: usize) -> Result<(), ParseError> {
+    let Expression::Union(terms) = expression;
+    for Terms::Concat(t) in terms {
+        for t in t {
+            let (f, suffix) = match t {
+                Term::Factor(f) => (f, None),
+                Term::WithSuffix(f, suffix) => (f, Some(suffix)),
+            };
+            match suffix {
+                Some(&TermSuffix::Range(min, max)) if min > max => {
+                    return Err(ParseError::InvalidRepeatRange { min, max });
+                }
+                Some(
+                    &TermSuffix::Range(_, count)
+                    | &TermSuffix::OpenRange(count)
+                    | &TermSuffix::Repeat(count),
+                ) if count > limit => {
+                    return Err(ParseError::RepeatTooLarge { count, limit });
+                }
+                _ => {}
+            }
+            if let Factor::Group(e) | Factor::FixedGroup(e) = f {
+                check_repeats(e, limit)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `config::PARSER_LIMITS` の制限のもとでパターンを解析する
+pub fn parse(s: &str) -> Result<Expression, ParseError> {
+    parse_with_limits(s, &PARSER_LIMITS)
+}
+
+pub fn parse_with_limits(s: &str, limits: &Limits) -> Result<Expression, ParseError> {
+    let length = s.chars().count();
+    if length > limits.pattern_length {
+        return Err(ParseError::TooLong {
+            length,
+            limit: limits.pattern_length,
+        });
+    }
+    let depth = nesting_depth(s);
+    if depth > limits.depth {
+        return Err(ParseError::TooDeep {
+            depth,
+            limit: limits.depth,
+        });
+    }
+
+    let position = |rest: &str| s[..s.len() - rest.len()].chars().count();
+    let expression = match expression(s) {
+        Ok(("", expression)) => expression,
+        Ok((rest, _)) => {
+            return Err(ParseError::Syntax {
+                position: position(rest),
+            })
+        }
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+            return Err(ParseError::Syntax {
+                position: position(e.input),
+            })
+        }
+        Err(nom::Err::Incomplete(_)) => return Err(ParseError::Syntax { position: length }),
+    };
+
+    let count = node_count(&expression);
+    if count > limits.nodes {
+        return Err(ParseError::TooManyNodes {
+            count,
+            limit: limits.nodes,
+        });
+    }
+    check_repeats(&expression, limits.repeat)?;
+
+    Ok(expression)
 }
 
 #[cfg(test)]
@@ -376,4 +580,61 @@ mod tests {
         assert_eq!(term_suffix("{5,10}"), Ok(("", TermSuffix::Range(5, 10))));
         assert_eq!(term_suffix("{100}"), Ok(("", TermSuffix::Repeat(100))));
     }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("ab[c"), Err(ParseError::Syntax { position: 2 }));
+        assert_eq!(parse("(a"), Err(ParseError::Syntax { position: 0 }));
+        assert_eq!(
+            parse("a{5,3}"),
+            Err(ParseError::InvalidRepeatRange { min: 5, max: 3 })
+        );
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = Limits {
+            pattern_length: 30,
+            depth: 3,
+            nodes: 30,
+            repeat: 100,
+        };
+        assert_eq!(
+            parse_with_limits(&"a".repeat(31), &limits),
+            Err(ParseError::TooLong {
+                length: 31,
+                limit: 30
+            })
+        );
+        assert_eq!(
+            parse_with_limits("((((a))))", &limits),
+            Err(ParseError::TooDeep { depth: 4, limit: 3 })
+        );
+        assert!(parse_with_limits(r"\(\(<[\<]((a))>", &limits).is_ok());
+        assert!(matches!(
+            parse_with_limits(&"a".repeat(15), &limits),
+            Err(ParseError::TooManyNodes {
+                count: 32,
+                limit: 30
+            })
+        ));
+        assert_eq!(
+            parse_with_limits("(a{0,18446744073709551615})", &limits),
+            Err(ParseError::RepeatTooLarge {
+                count: 18446744073709551615,
+                limit: 100
+            })
+        );
+        assert!(parse_with_limits("<a>{100}", &limits).is_ok());
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        for s in [
+            r"[a-z\]]{3}(a|b)*<c>+d?e{2,}\\",
+            "hoge|fuga{1,3}|<[あ-お]>{2}",
+        ] {
+            assert_eq!(parse(s).unwrap().to_string(), s);
+        }
+    }
 }
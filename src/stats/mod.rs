@@ -0,0 +1,810 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use crate::generator::TimedOut;
+use crate::model::{ClassElement, Expression, Factor, Literal, Term, TermSuffix, Terms, Token};
+
+/// 繰り返しの確率を足し合わせるときに、これより小さい寄与は打ち切る
+const EPSILON: f64 = 1e-20;
+
+/// 無限に続く繰り返しの計算を打ち切る回数
+const MAX_ITERATIONS: usize = 100_000;
+
+/// `outcomes` で列挙する文字列の最大の長さ
+const MAX_OUTCOME_LENGTH: usize = 10_000;
+
+/// 計算を打ち切る時刻
+///
+/// 入れ子の繰り返しで計算量が爆発しても、スレッドが回り続けないように計算中に持ち回す
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    timeout: Duration,
+    at: Instant,
+}
+
+impl Deadline {
+    /// 今から `timeout` が経過したら打ち切る
+    pub fn after(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            at: Instant::now() + timeout,
+        }
+    }
+
+    fn check(&self) -> Result<(), TimedOut> {
+        if Instant::now() > self.at {
+            Err(TimedOut {
+                timeout: self.timeout,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// `Generate` と同じ意味論で、パターンを解析する
+///
+/// `forward` は、開始位置の確率分布を受け取り、
+/// `s` のその位置から生成を始めたときに、生成した文字列が `s` と一致したまま終わる位置の確率分布を返す
+/// `forward` と `outcomes` は `deadline` を過ぎると `TimedOut` を返す
+pub trait Analyze {
+    fn forward(&self, s: &[char], dist: &[f64], deadline: &Deadline) -> Result<Vec<f64>, TimedOut>;
+    fn expected_length(&self) -> f64;
+    fn min_length(&self) -> usize;
+    /// 上限がない場合は `None` を返す
+    fn max_length(&self) -> Option<usize>;
+    /// 生成されうる文字列とその確率を列挙する
+    /// 無限にある場合や、`limit` 個を超える場合は `None` を返す
+    fn outcomes(&self, limit: usize, deadline: &Deadline) -> Result<Option<Outcomes>, TimedOut>;
+    /// 生成のされ方の数
+    /// 異なる生成のされ方で同じ文字列ができる場合も別々に数えるので、文字列の種類の数の上限になる
+    fn size(&self) -> Size;
+}
+
+/// 生成されうる文字列とその確率
+pub type Outcomes = Vec<(String, f64)>;
+
+/// 生成されうる文字列の数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Size {
+    Finite(u128),
+    /// 有限だが、`u128` で表せないほど多い
+    TooMany,
+    Infinite,
+}
+
+impl Size {
+    fn add(self, other: Size) -> Size {
+        match (self, other) {
+            (Size::Infinite, _) | (_, Size::Infinite) => Size::Infinite,
+            (Size::Finite(a), Size::Finite(b)) => {
+                a.checked_add(b).map_or(Size::TooMany, Size::Finite)
+            }
+            _ => Size::TooMany,
+        }
+    }
+
+    fn mul(self, other: Size) -> Size {
+        match (self, other) {
+            (Size::Finite(0), _) | (_, Size::Finite(0)) => Size::Finite(0),
+            (Size::Infinite, _) | (_, Size::Infinite) => Size::Infinite,
+            (Size::Finite(a), Size::Finite(b)) => {
+                a.checked_mul(b).map_or(Size::TooMany, Size::Finite)
+            }
+            _ => Size::TooMany,
+        }
+    }
+}
+
+/// `expression` が `s` をちょうど生成する確率
+pub fn probability(expression: &Expression, s: &str, deadline: &Deadline) -> Result<f64, TimedOut> {
+    let s = s.chars().collect::<Vec<_>>();
+    let mut dist = vec![0.0; s.len() + 1];
+    dist[0] = 1.0;
+    Ok(expression.forward(&s, &dist, deadline)?[s.len()])
+}
+
+/// 生成されやすい文字列を、確率の高い順に最大 `k` 個返す
+/// 生成されうる文字列が無限にある場合や、多すぎる場合は `None` を返す
+pub fn top_outputs(
+    expression: &Expression,
+    k: usize,
+    limit: usize,
+    deadline: &Deadline,
+) -> Result<Option<Outcomes>, TimedOut> {
+    let Some(mut outcomes) = expression.outcomes(limit, deadline)? else {
+        return Ok(None);
+    };
+    outcomes.sort_by(|(a, p), (b, q)| q.total_cmp(p).then_with(|| a.cmp(b)));
+    outcomes.truncate(k);
+    Ok(Some(outcomes))
+}
+
+fn merge(outcomes: impl IntoIterator<Item = (String, f64)>) -> Outcomes {
+    let mut merged = HashMap::<String, f64>::new();
+    for (s, p) in outcomes {
+        *merged.entry(s).or_default() += p;
+    }
+    merged.into_iter().collect()
+}
+
+fn concat(a: &[(String, f64)], b: &[(String, f64)], limit: usize) -> Option<Outcomes> {
+    if a.len().saturating_mul(b.len()) > limit {
+        return None;
+    }
+    Some(merge(a.iter().flat_map(|(s, p)| {
+        b.iter().map(move |(t, q)| (format!("{}{}", s, t), p * q))
+    })))
+}
+
+fn shift(s: &[char], dist: &[f64], prob: impl Fn(char) -> f64) -> Vec<f64> {
+    let mut res = vec![0.0; dist.len()];
+    for (i, &p) in dist.iter().enumerate() {
+        if p == 0.0 || i >= s.len() {
+            continue;
+        }
+        res[i + 1] += p * prob(s[i]);
+    }
+    res
+}
+
+/// 0.5 の `n` 乗
+fn half_pow(n: usize) -> f64 {
+    0.5f64.powi(n.min(i32::MAX as usize) as i32)
+}
+
+/// `a..=b` に含まれる整数の個数
+fn range_width(a: usize, b: usize) -> f64 {
+    b as f64 - a as f64 + 1.0
+}
+
+fn add_scaled(acc: &mut [f64], v: &[f64], scale: f64) {
+    for (a, x) in acc.iter_mut().zip(v) {
+        *a += x * scale;
+    }
+}
+
+impl Literal {
+    /// この文字が 1 回の生成で `c` になる確率
+    pub fn char_probability(&self, c: char) -> f64 {
+        match *self {
+            Literal::Char(x) => (x == c) as u8 as f64,
+            Literal::Escape('d') => c.is_ascii_digit() as u8 as f64 / 10.0,
+            Literal::Escape('w') => c.is_ascii_alphanumeric() as u8 as f64 / 62.0,
+            Literal::Escape(x) if x.is_ascii_alphanumeric() => 0.0,
+            Literal::Escape(x) => (x == c) as u8 as f64,
+        }
+    }
+
+    fn chars(&self) -> Vec<char> {
+        match *self {
+            Literal::Char(x) => vec![x],
+            Literal::Escape('d') => ('0'..='9').collect(),
+            Literal::Escape('w') => ('a'..='z').chain('A'..='Z').chain('0'..='9').collect(),
+            Literal::Escape(x) if x.is_ascii_alphanumeric() => vec![],
+            Literal::Escape(x) => vec![x],
+        }
+    }
+}
+
+impl ClassElement {
+    fn char_probability(&self, c: char) -> f64 {
+        match self {
+            ClassElement::Range(a, b) => (*a..=*b).contains(&c) as u8 as f64 / self.size() as f64,
+            ClassElement::Literal(l) => l.char_probability(c),
+        }
+    }
+}
+
+impl Token {
+    pub fn char_probability(&self, c: char) -> f64 {
+        match self {
+            Token::Literal(l) => l.char_probability(c),
+            Token::Class(elements) => {
+                let sum = elements.iter().map(|e| e.size()).sum::<usize>() as f64;
+                elements
+                    .iter()
+                    .map(|e| e.size() as f64 / sum * e.char_probability(c))
+                    .sum()
+            }
+        }
+    }
+}
+
+impl Analyze for Token {
+    fn forward(&self, s: &[char], dist: &[f64], deadline: &Deadline) -> Result<Vec<f64>, TimedOut> {
+        deadline.check()?;
+        Ok(shift(s, dist, |c| self.char_probability(c)))
+    }
+
+    fn expected_length(&self) -> f64 {
+        1.0
+    }
+
+    fn min_length(&self) -> usize {
+        1
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn size(&self) -> Size {
+        match self {
+            Token::Literal(l) => Size::Finite(l.chars().len() as u128),
+            Token::Class(elements) => Size::Finite(
+                elements
+                    .iter()
+                    .map(|e| match e {
+                        ClassElement::Literal(l) => l.chars().len() as u128,
+                        e => e.size() as u128,
+                    })
+                    .sum(),
+            ),
+        }
+    }
+
+    fn outcomes(&self, limit: usize, deadline: &Deadline) -> Result<Option<Outcomes>, TimedOut> {
+        deadline.check()?;
+        let chars = match self {
+            Token::Literal(l) => l.chars(),
+            Token::Class(elements) => {
+                if elements.iter().map(|e| e.size()).sum::<usize>() > limit {
+                    return Ok(None);
+                }
+                elements
+                    .iter()
+                    .flat_map(|e| match e {
+                        ClassElement::Range(a, b) => (*a..=*b).collect(),
+                        ClassElement::Literal(l) => l.chars(),
+                    })
+                    .collect()
+            }
+        };
+        if chars.len() > limit {
+            return Ok(None);
+        }
+        // 重複した文字の確率は `char_probability` で既に足し合わされている
+        let chars = chars.into_iter().collect::<HashSet<_>>();
+        Ok(Some(
+            chars
+                .into_iter()
+                .map(|c| (c.to_string(), self.char_probability(c)))
+                .collect(),
+        ))
+    }
+}
+
+impl Analyze for Factor {
+    fn forward(&self, s: &[char], dist: &[f64], deadline: &Deadline) -> Result<Vec<f64>, TimedOut> {
+        match self {
+            Factor::Token(t) => t.forward(s, dist, deadline),
+            Factor::Group(e) | Factor::FixedGroup(e) => e.forward(s, dist, deadline),
+        }
+    }
+
+    fn expected_length(&self) -> f64 {
+        match self {
+            Factor::Token(t) => t.expected_length(),
+            Factor::Group(e) | Factor::FixedGroup(e) => e.expected_length(),
+        }
+    }
+
+    fn min_length(&self) -> usize {
+        match self {
+            Factor::Token(t) => t.min_length(),
+            Factor::Group(e) | Factor::FixedGroup(e) => e.min_length(),
+        }
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        match self {
+            Factor::Token(t) => t.max_length(),
+            Factor::Group(e) | Factor::FixedGroup(e) => e.max_length(),
+        }
+    }
+
+    fn outcomes(&self, limit: usize, deadline: &Deadline) -> Result<Option<Outcomes>, TimedOut> {
+        match self {
+            Factor::Token(t) => t.outcomes(limit, deadline),
+            Factor::Group(e) | Factor::FixedGroup(e) => e.outcomes(limit, deadline),
+        }
+    }
+
+    fn size(&self) -> Size {
+        match self {
+            Factor::Token(t) => t.size(),
+            Factor::Group(e) | Factor::FixedGroup(e) => e.size(),
+        }
+    }
+}
+
+impl TermSuffix {
+    /// 繰り返し回数がちょうど `n` になる確率
+    fn count_probability(&self, n: usize) -> f64 {
+        match *self {
+            TermSuffix::Question if n <= 1 => 0.5,
+            TermSuffix::Question => 0.0,
+            TermSuffix::Asterisk => half_pow(n + 1),
+            TermSuffix::Plus if n >= 1 => half_pow(n),
+            TermSuffix::Plus => 0.0,
+            TermSuffix::Range(a, b) if (a..=b).contains(&n) => 1.0 / range_width(a, b),
+            TermSuffix::Range(_, _) => 0.0,
+            TermSuffix::OpenRange(a) if n >= a => half_pow(n - a + 1),
+            TermSuffix::OpenRange(_) => 0.0,
+            TermSuffix::Repeat(m) => (n == m) as u8 as f64,
+        }
+    }
+
+    /// 繰り返し回数が `n` より大きくなる確率
+    fn tail_probability(&self, n: usize) -> f64 {
+        match *self {
+            TermSuffix::Question if n == 0 => 0.5,
+            TermSuffix::Question => 0.0,
+            TermSuffix::Asterisk => half_pow(n + 1),
+            TermSuffix::Plus => half_pow(n),
+            TermSuffix::Range(a, _) if n < a => 1.0,
+            TermSuffix::Range(a, b) if n < b => (b - n) as f64 / range_width(a, b),
+            TermSuffix::Range(_, _) => 0.0,
+            TermSuffix::OpenRange(a) if n < a => 1.0,
+            TermSuffix::OpenRange(a) => half_pow(n - a + 1),
+            TermSuffix::Repeat(m) => (n < m) as u8 as f64,
+        }
+    }
+
+    pub fn min_count(&self) -> usize {
+        match *self {
+            TermSuffix::Question | TermSuffix::Asterisk => 0,
+            TermSuffix::Plus => 1,
+            TermSuffix::Range(a, _) | TermSuffix::OpenRange(a) | TermSuffix::Repeat(a) => a,
+        }
+    }
+
+    /// 上限がない場合は `None` を返す
+    pub fn max_count(&self) -> Option<usize> {
+        match *self {
+            TermSuffix::Question => Some(1),
+            TermSuffix::Asterisk | TermSuffix::Plus | TermSuffix::OpenRange(_) => None,
+            TermSuffix::Range(_, b) | TermSuffix::Repeat(b) => Some(b),
+        }
+    }
+
+    fn expected_count(&self) -> f64 {
+        match *self {
+            TermSuffix::Question => 0.5,
+            TermSuffix::Asterisk => 1.0,
+            TermSuffix::Plus => 2.0,
+            TermSuffix::Range(a, b) => (a as f64 + b as f64) / 2.0,
+            TermSuffix::OpenRange(a) => a as f64 + 1.0,
+            TermSuffix::Repeat(n) => n as f64,
+        }
+    }
+}
+
+/// 毎回独立に生成する繰り返しの `forward`
+fn repeat_forward(
+    f: &Factor,
+    suffix: &TermSuffix,
+    s: &[char],
+    dist: &[f64],
+    deadline: &Deadline,
+) -> Result<Vec<f64>, TimedOut> {
+    let mut res = vec![0.0; dist.len()];
+    let mut v = dist.to_vec();
+    let mut n = 0;
+    loop {
+        // 入れ子の繰り返しでは回数の積だけ回るので、1 回ごとに確かめる
+        deadline.check()?;
+        add_scaled(&mut res, &v, suffix.count_probability(n));
+
+        let tail = suffix.tail_probability(n);
+        let mass = v.iter().sum::<f64>();
+        if tail == 0.0 || mass == 0.0 || n >= MAX_ITERATIONS {
+            break;
+        }
+        if n >= suffix.min_count() && tail * mass < EPSILON * res.iter().sum::<f64>() {
+            break;
+        }
+
+        let next = f.forward(s, &v, deadline)?;
+        if next == v {
+            // これ以上繰り返しても分布が変わらないので、残りの確率をまとめて足す
+            // 最小回数に達していなくても、残りの回数ではすべて同じ分布になる
+            add_scaled(&mut res, &v, tail);
+            break;
+        }
+        v = next;
+        n += 1;
+    }
+    Ok(res)
+}
+
+/// 1 度だけ生成した文字列を繰り返す、固定グループの `forward`
+fn fixed_repeat_forward(
+    f: &Factor,
+    suffix: &TermSuffix,
+    s: &[char],
+    dist: &[f64],
+    deadline: &Deadline,
+) -> Result<Vec<f64>, TimedOut> {
+    let mut res = vec![0.0; dist.len()];
+    for (i, &p) in dist.iter().enumerate() {
+        if p == 0.0 {
+            continue;
+        }
+        // 0 回の繰り返しは、何が生成されても空文字列になる
+        res[i] += p * suffix.count_probability(0);
+
+        let mut start = vec![0.0; dist.len()];
+        start[i] = 1.0;
+        let once = f.forward(s, &start, deadline)?;
+        // 空文字列が生成された場合は、何回繰り返しても空文字列になる
+        res[i] += p * once[i] * suffix.tail_probability(0);
+
+        for (k, &q) in once.iter().enumerate().skip(i + 1) {
+            if q == 0.0 {
+                continue;
+            }
+            let width = k - i;
+            let mut end = k;
+            let mut n = 1;
+            loop {
+                res[end] += p * q * suffix.count_probability(n);
+                if suffix.tail_probability(n) == 0.0 || end + width > s.len() {
+                    break;
+                }
+                if s[end..end + width] != s[i..k] {
+                    break;
+                }
+                end += width;
+                n += 1;
+            }
+        }
+    }
+    Ok(res)
+}
+
+impl Analyze for Term {
+    fn forward(&self, s: &[char], dist: &[f64], deadline: &Deadline) -> Result<Vec<f64>, TimedOut> {
+        match self {
+            Term::Factor(f) => f.forward(s, dist, deadline),
+            Term::WithSuffix(f @ Factor::FixedGroup(_), suffix) => {
+                fixed_repeat_forward(f, suffix, s, dist, deadline)
+            }
+            Term::WithSuffix(f, suffix) => repeat_forward(f, suffix, s, dist, deadline),
+        }
+    }
+
+    fn expected_length(&self) -> f64 {
+        match self {
+            Term::Factor(f) => f.expected_length(),
+            Term::WithSuffix(f, suffix) => suffix.expected_count() * f.expected_length(),
+        }
+    }
+
+    fn min_length(&self) -> usize {
+        match self {
+            Term::Factor(f) => f.min_length(),
+            Term::WithSuffix(f, suffix) => suffix.min_count().saturating_mul(f.min_length()),
+        }
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        match self {
+            Term::Factor(f) => f.max_length(),
+            Term::WithSuffix(f, suffix) => match (f.max_length()?, suffix.max_count()) {
+                (0, _) => Some(0),
+                (m, Some(n)) => Some(m.saturating_mul(n)),
+                (_, None) => None,
+            },
+        }
+    }
+
+    fn outcomes(&self, limit: usize, deadline: &Deadline) -> Result<Option<Outcomes>, TimedOut> {
+        match self {
+            Term::Factor(f) => f.outcomes(limit, deadline),
+            Term::WithSuffix(f, suffix) => {
+                let (Some(max_length), Some(max)) = (self.max_length(), suffix.max_count()) else {
+                    return Ok(None);
+                };
+                if max_length > MAX_OUTCOME_LENGTH {
+                    return Ok(None);
+                }
+                let min = suffix.min_count();
+                let Some(base) = f.outcomes(limit, deadline)? else {
+                    return Ok(None);
+                };
+
+                let mut res = Vec::new();
+                match f {
+                    Factor::FixedGroup(_) => {
+                        for n in min..=max {
+                            deadline.check()?;
+                            let p = suffix.count_probability(n);
+                            res.extend(base.iter().map(|(s, q)| (s.repeat(n), p * q)));
+                            if res.len() > limit {
+                                return Ok(None);
+                            }
+                        }
+                    }
+                    _ => {
+                        let mut current = vec![(String::new(), 1.0)];
+                        for _ in 0..min {
+                            deadline.check()?;
+                            let Some(next) = concat(&current, &base, limit) else {
+                                return Ok(None);
+                            };
+                            current = next;
+                        }
+                        for n in min..=max {
+                            deadline.check()?;
+                            let p = suffix.count_probability(n);
+                            res.extend(current.iter().map(|(s, q)| (s.clone(), p * q)));
+                            if res.len() > limit {
+                                return Ok(None);
+                            }
+                            if n < max {
+                                let Some(next) = concat(&current, &base, limit) else {
+                                    return Ok(None);
+                                };
+                                current = next;
+                            }
+                        }
+                    }
+                }
+                Ok(Some(merge(res)))
+            }
+        }
+    }
+
+    fn size(&self) -> Size {
+        match self {
+            Term::Factor(f) => f.size(),
+            Term::WithSuffix(f, suffix) => {
+                let base = f.size();
+                if f.max_length() == Some(0) {
+                    return base.mul(Size::Finite(1));
+                }
+                let min = suffix.min_count();
+                let Some(max) = suffix.max_count() else {
+                    return Size::Infinite;
+                };
+                if let Factor::FixedGroup(_) = f {
+                    // 0 回の場合は何を生成しても空文字列になる
+                    let zero = Size::Finite((min == 0) as u128);
+                    let counts = (max + 1).saturating_sub(min.max(1)) as u128;
+                    return zero.add(base.mul(Size::Finite(counts)));
+                }
+
+                let mut total = Size::Finite(0);
+                let mut power = Size::Finite(1);
+                for n in 0..=max {
+                    if n >= min {
+                        total = total.add(power);
+                    }
+                    if total == Size::TooMany {
+                        break;
+                    }
+                    power = power.mul(base);
+                }
+                total
+            }
+        }
+    }
+}
+
+impl Analyze for Terms {
+    fn forward(&self, s: &[char], dist: &[f64], deadline: &Deadline) -> Result<Vec<f64>, TimedOut> {
+        match self {
+            Terms::Concat(t) => t
+                .iter()
+                .try_fold(dist.to_vec(), |dist, t| t.forward(s, &dist, deadline)),
+        }
+    }
+
+    fn expected_length(&self) -> f64 {
+        match self {
+            Terms::Concat(t) => t.iter().map(|t| t.expected_length()).sum(),
+        }
+    }
+
+    fn min_length(&self) -> usize {
+        match self {
+            Terms::Concat(t) => t
+                .iter()
+                .fold(0usize, |acc, t| acc.saturating_add(t.min_length())),
+        }
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        match self {
+            Terms::Concat(t) => t.iter().try_fold(0usize, |acc, t| {
+                t.max_length().map(|m| acc.saturating_add(m))
+            }),
+        }
+    }
+
+    fn outcomes(&self, limit: usize, deadline: &Deadline) -> Result<Option<Outcomes>, TimedOut> {
+        match self {
+            Terms::Concat(t) => {
+                let mut res = vec![(String::new(), 1.0)];
+                for t in t {
+                    let Some(next) = t
+                        .outcomes(limit, deadline)?
+                        .and_then(|o| concat(&res, &o, limit))
+                    else {
+                        return Ok(None);
+                    };
+                    res = next;
+                }
+                Ok(Some(res))
+            }
+        }
+    }
+
+    fn size(&self) -> Size {
+        match self {
+            Terms::Concat(t) => t.iter().fold(Size::Finite(1), |acc, t| acc.mul(t.size())),
+        }
+    }
+}
+
+impl Analyze for Expression {
+    fn forward(&self, s: &[char], dist: &[f64], deadline: &Deadline) -> Result<Vec<f64>, TimedOut> {
+        match self {
+            Expression::Union(terms) => {
+                let p = 1.0 / terms.len() as f64;
+                let mut res = vec![0.0; dist.len()];
+                for t in terms {
+                    add_scaled(&mut res, &t.forward(s, dist, deadline)?, p);
+                }
+                Ok(res)
+            }
+        }
+    }
+
+    fn expected_length(&self) -> f64 {
+        match self {
+            Expression::Union(t) => {
+                t.iter().map(|t| t.expected_length()).sum::<f64>() / t.len() as f64
+            }
+        }
+    }
+
+    fn min_length(&self) -> usize {
+        match self {
+            Expression::Union(t) => t.iter().map(|t| t.min_length()).min().unwrap_or(0),
+        }
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        match self {
+            Expression::Union(t) => t
+                .iter()
+                .try_fold(0usize, |acc, t| t.max_length().map(|m| acc.max(m))),
+        }
+    }
+
+    fn outcomes(&self, limit: usize, deadline: &Deadline) -> Result<Option<Outcomes>, TimedOut> {
+        match self {
+            Expression::Union(terms) => {
+                let p = 1.0 / terms.len() as f64;
+                let mut res = Vec::new();
+                for t in terms {
+                    let Some(outcomes) = t.outcomes(limit, deadline)? else {
+                        return Ok(None);
+                    };
+                    res.extend(outcomes.into_iter().map(|(s, q)| (s, p * q)));
+                    if res.len() > limit {
+                        return Ok(None);
+                    }
+                }
+                Ok(Some(merge(res)))
+            }
+        }
+    }
+
+    fn size(&self) -> Size {
+        match self {
+            Expression::Union(terms) => terms
+                .iter()
+                .fold(Size::Finite(0), |acc, t| acc.add(t.size())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-12, "{} != {}", a, b);
+    }
+
+    fn no_deadline() -> Deadline {
+        Deadline::after(Duration::from_secs(3600))
+    }
+
+    fn prob(pattern: &str, s: &str) -> f64 {
+        probability(&parse(pattern).unwrap(), s, &no_deadline()).unwrap()
+    }
+
+    #[test]
+    fn test_probability_basic() {
+        assert_close(prob("abc", "abc"), 1.0);
+        assert_close(prob("abc", "abd"), 0.0);
+        assert_close(prob("a|b|c", "b"), 1.0 / 3.0);
+        assert_close(prob("[a-z]", "q"), 1.0 / 26.0);
+        assert_close(prob("[aab]", "a"), 2.0 / 3.0);
+        assert_close(prob(r"\d", "7"), 0.1);
+    }
+
+    #[test]
+    fn test_probability_repeat() {
+        assert_close(prob("a*", ""), 0.5);
+        assert_close(prob("a*", "aaa"), 0.0625);
+        assert_close(prob("a+", "a"), 0.5);
+        assert_close(prob("a?", "a"), 0.5);
+        assert_close(prob("a{2,4}", "aaa"), 1.0 / 3.0);
+        assert_close(prob("a{2,}", "aaa"), 0.25);
+        assert_close(prob("(a|aa)*", "aa"), 0.125 + 0.03125);
+        assert_close(prob("(((a{0}){10000}){10000}){10000}", ""), 1.0);
+        assert_close(prob("((a{0}){10000}b){3}", "bbb"), 1.0);
+    }
+
+    #[test]
+    fn test_probability_fixed_group() {
+        assert_close(prob("<[ab]>{2}", "aa"), 0.5);
+        assert_close(prob("<[ab]>{2}", "ab"), 0.0);
+        assert_close(prob("<a?>*", ""), 0.5 + 0.5 * 0.5);
+    }
+
+    #[test]
+    fn test_lengths() {
+        let e = parse("ab(c|de)*f?").unwrap();
+        assert_close(e.expected_length(), 2.0 + 1.5 + 0.5);
+        assert_eq!(e.min_length(), 2);
+        assert_eq!(e.max_length(), None);
+        assert_eq!(parse("a{2,5}b?").unwrap().max_length(), Some(6));
+    }
+
+    #[test]
+    fn test_size() {
+        let size = |s: &str| parse(s).unwrap().size();
+        assert_eq!(size("[a-z]{3}[A-Z]"), Size::Finite(26u128.pow(4)));
+        assert_eq!(size("hoge|fuga|[ab]?"), Size::Finite(5));
+        assert_eq!(size("<[ab]>{0,2}"), Size::Finite(5));
+        assert_eq!(size("a*"), Size::Infinite);
+        assert_eq!(size("(a{0})*"), Size::Finite(1));
+        assert_eq!(size("[a-z]{100}"), Size::TooMany);
+    }
+
+    #[test]
+    fn test_top_outputs() {
+        let e = parse("a(b|cc?)").unwrap();
+        let top = top_outputs(&e, 2, 100, &no_deadline()).unwrap().unwrap();
+        assert_eq!(top[0].0, "ab");
+        assert_close(top[0].1, 0.5);
+        assert_eq!(top.len(), 2);
+        assert_eq!(
+            top_outputs(&parse("a*").unwrap(), 2, 100, &no_deadline()),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_probability_timeout() {
+        // 入れ子ごとに計算量が何倍にもなるので、打ち切らないと終わらない
+        let e = parse(&format!("{}a?{}", "(".repeat(8), "){0,10000}".repeat(8))).unwrap();
+        let timeout = Duration::from_millis(50);
+        let start = Instant::now();
+        assert_eq!(
+            probability(&e, "", &Deadline::after(timeout)),
+            Err(TimedOut { timeout })
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}